@@ -38,7 +38,7 @@ fn main() -> Result<(), Error> {
 
 fn start_server<A: ToSocketAddrs>(addr: A, redis_url: &str, header: &str) -> Result<(), Error> {
     let limiter = web::Data::new(
-        Limiter::build(&redis_url)
+        Limiter::build(redis_url)
             .limit(5)
             .period(Duration::from_secs(10))
             .finish()?,
@@ -70,7 +70,6 @@ fn hello() -> impl Future<Item = HttpResponse, Error = actix_web::Error> {
 fn emit_preamble(addr: &str, header: &str) {
     println!(
         "\
-
 # Catch All Example
 
 This service responds to all HTTP verbs on all paths identically with a plain
@@ -81,7 +80,7 @@ trigger the rate-limiting middleware:
 
      curl -v -H \"{header}: token MYTOKEN\" http://{addr}
 
-If the '{}' header is missing, the service will return an
+If the '{header}' header is missing, the service will return an
 HTTP/403 Forbidden response:
 
      curl -v http://{addr}
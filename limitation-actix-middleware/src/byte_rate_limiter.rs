@@ -0,0 +1,322 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::rate_limiter::{add_retry_after_header, key};
+use crate::trace;
+use actix_web::{
+    dev::{Body, BodySize, MessageBody, Payload, ResponseBody, Service, ServiceRequest},
+    dev::{ServiceResponse, Transform},
+    error::PayloadError,
+    web::Bytes,
+    Error, HttpMessage, HttpResponse,
+};
+use futures::{
+    future::{self, Either, FutureResult},
+    try_ready, Async, Future, Poll, Stream,
+};
+use limitation::{ByteLimiter, Error as LError};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use tokio_current_thread::TaskExecutor;
+
+/// The direction of the bytes charged to a key by the [`ByteRateLimiter`] middleware.
+///
+/// [`ByteRateLimiter`]: struct.ByteRateLimiter.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transfer {
+    /// Charges the size of the request body.
+    Upload,
+    /// Charges the size of the response body.
+    Download,
+    /// Charges the size of both the request and response bodies.
+    Both,
+}
+
+/// `Middleware` for limiting the bytes transferred on a key in a period, using a byte budget keyed
+/// on a `HeaderName`.
+///
+/// A request is rejected once its key's budget is spent. Otherwise it's passed down the chain and
+/// its key is charged for the transfer once the response body has been sent. The bytes charged are
+/// those actually read from the request payload and written from the response body, so chunked
+/// uploads and streamed downloads are charged in full whatever their `Content-Length` headers
+/// claim. A response which is dropped before it's finished, such as when the client disconnects,
+/// is charged for the bytes transferred up to that point.
+///
+/// The middleware holds its own [`ByteLimiter`], so separate upload and download quotas can be
+/// enforced by wrapping an app twice. The key header is taken from the `HeaderName` in the app
+/// data, and backend failures are handled according to the Limiter's [`FailurePolicy`], as with
+/// the [`RateLimiter`] middleware. A charge which fails is only logged, as the response has
+/// already been sent.
+///
+/// # Example
+///
+/// ```no_run
+/// use actix_web::{http::header::HeaderName, web, App, HttpResponse};
+/// use limitation_actix_middleware::{ByteLimiter, ByteRateLimiter, Limiter, Transfer};
+/// use std::time::Duration;
+///
+/// let header = web::Data::new(HeaderName::from_static("authorization"));
+/// // 100 MiB of downloads per hour
+/// let downloads = ByteLimiter::new(
+///     Limiter::build("redis://127.0.0.1/")
+///         .limit(100 * 1024 * 1024)
+///         .period(Duration::from_secs(60 * 60))
+///         .finish()?,
+/// );
+///
+/// let app = App::new()
+///     .register_data(header.clone())
+///     .wrap(ByteRateLimiter::new(downloads, Transfer::Download))
+///     .service(web::resource("/test").route(web::get().to(|| HttpResponse::Ok())));
+/// # Ok::<(), limitation_actix_middleware::Error>(())
+/// ```
+///
+/// [`ByteLimiter`]: struct.ByteLimiter.html
+/// [`FailurePolicy`]: enum.FailurePolicy.html
+/// [`RateLimiter`]: struct.RateLimiter.html
+#[derive(Clone)]
+pub struct ByteRateLimiter {
+    /// The byte budgets to check and charge
+    limiter: ByteLimiter,
+    /// The bytes to charge for each request
+    transfer: Transfer,
+}
+
+impl ByteRateLimiter {
+    /// Creates a new `ByteRateLimiter` which charges each key for the given transfer.
+    pub fn new(limiter: ByteLimiter, transfer: Transfer) -> Self {
+        ByteRateLimiter { limiter, transfer }
+    }
+}
+
+impl<S, B> Transform<S> for ByteRateLimiter
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ByteRateLimiterMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        future::ok(ByteRateLimiterMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            limiter: self.limiter.clone(),
+            transfer: self.transfer,
+        })
+    }
+}
+
+pub struct ByteRateLimiterMiddleware<S> {
+    // TODO: The service is reference counted so that it can be cloned into a Future for later
+    // execution.  This should be avoidable if/when the library is upgraded to to use
+    // *async/await*.
+    service: Rc<RefCell<S>>,
+    limiter: ByteLimiter,
+    transfer: Transfer,
+}
+
+impl<S, B> Service for ByteRateLimiterMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limiter = self.limiter.clone();
+        let transfer = self.transfer;
+        let key = match key(&req) {
+            Some(key) => key,
+            None => {
+                trace::missing_key(&req);
+                return Box::new(future::ok(
+                    req.into_response(HttpResponse::Forbidden().finish().into_body()),
+                ));
+            }
+        };
+        // Bytes are counted as the handler reads them, so they're only known once it's done
+        let uploaded = Rc::new(Cell::new(0));
+        if let Transfer::Upload | Transfer::Both = transfer {
+            let payload = CountedPayload {
+                payload: req.take_payload(),
+                uploaded: uploaded.clone(),
+            };
+            req.set_payload(Payload::Stream(Box::new(payload)));
+        }
+
+        let span = trace::call_span(&req);
+        let future = self.limiter.check(key.clone()).then(move |result| {
+            match result {
+                Ok(status) => trace::allowed(&status),
+                Err(LError::LimitExceeded(status)) => {
+                    trace::denied(&status);
+                    let mut res = req.into_response(HttpResponse::Forbidden().finish().into_body());
+                    add_retry_after_header(&mut res, &status);
+                    return Either::A(future::ok(res));
+                }
+                // As with the RateLimiter middleware, a backend error which gets here is turned
+                // away, as the Limiter's failure policy is closed
+                Err(err) => {
+                    trace::backend_error(&err);
+                    return Either::A(future::ok(
+                        req.into_response(HttpResponse::ServiceUnavailable().finish().into_body()),
+                    ));
+                }
+            }
+
+            Either::B(service.borrow_mut().call(req).map(move |res| {
+                res.map_body(move |_, body| {
+                    ResponseBody::Other(Body::Message(Box::new(ChargedBody {
+                        body,
+                        downloads: transfer != Transfer::Upload,
+                        downloaded: 0,
+                        charge: Some(Charge {
+                            limiter,
+                            key,
+                            uploaded,
+                        }),
+                        charging: None,
+                    })))
+                })
+            }))
+        });
+
+        Box::new(trace::instrument(future, span))
+    }
+}
+
+/// A request payload which counts the bytes read from it.
+struct CountedPayload {
+    /// The original payload
+    payload: Payload,
+    /// The number of bytes read so far, shared with the response body
+    uploaded: Rc<Cell<usize>>,
+}
+
+impl Stream for CountedPayload {
+    type Item = Bytes;
+    type Error = PayloadError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let chunk = try_ready!(self.payload.poll());
+        if let Some(ref chunk) = chunk {
+            self.uploaded.set(self.uploaded.get() + chunk.len());
+        }
+
+        Ok(Async::Ready(chunk))
+    }
+}
+
+/// A pending charge for a request's transfer.
+struct Charge {
+    /// The byte budgets to charge
+    limiter: ByteLimiter,
+    /// The key to charge
+    key: String,
+    /// The number of bytes read from the request payload
+    uploaded: Rc<Cell<usize>>,
+}
+
+impl Charge {
+    /// Charges the key for its upload and the given number of downloaded bytes.
+    ///
+    /// A charge which fails is logged rather than failing the response, which has already been
+    /// sent.
+    fn run(self, downloaded: usize) -> Box<dyn Future<Item = (), Error = ()>> {
+        let bytes = self.uploaded.get() + downloaded;
+        if bytes == 0 {
+            return Box::new(future::ok(()));
+        }
+
+        Box::new(self.limiter.charge(self.key, bytes).then(|result| {
+            if let Err(err) = result {
+                trace::charge_failed(&err);
+            }
+            Ok(())
+        }))
+    }
+}
+
+/// A response body which counts the bytes written from it and charges for the transfer once it's
+/// finished.
+struct ChargedBody<B> {
+    /// The original body
+    body: ResponseBody<B>,
+    /// Whether the bytes written are charged
+    downloads: bool,
+    /// The number of bytes written so far
+    downloaded: usize,
+    /// The charge to make once the body is finished, taken when it's made
+    charge: Option<Charge>,
+    /// The charge being made, after the last chunk has been written
+    charging: Option<Box<dyn Future<Item = (), Error = ()>>>,
+}
+
+impl<B: MessageBody> MessageBody for ChargedBody<B> {
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(&mut self) -> Poll<Option<Bytes>, Error> {
+        if self.charging.is_none() {
+            // Once the charge has been made there's nothing left to write
+            let charge = match self.charge.take() {
+                Some(charge) => charge,
+                None => return Ok(Async::Ready(None)),
+            };
+            match self.body.poll_next() {
+                Ok(Async::Ready(None)) => self.charging = Some(charge.run(self.downloaded)),
+                polled => {
+                    self.charge = Some(charge);
+                    if let Ok(Async::Ready(Some(ref chunk))) = polled {
+                        if self.downloads {
+                            self.downloaded += chunk.len();
+                        }
+                    }
+                    return polled;
+                }
+            }
+        }
+
+        // The response only finishes once it's been charged, so the key's next request sees it
+        if let Some(ref mut charging) = self.charging {
+            if let Ok(Async::NotReady) = charging.poll() {
+                return Ok(Async::NotReady);
+            }
+        }
+        self.charging = None;
+
+        Ok(Async::Ready(None))
+    }
+}
+
+impl<B> Drop for ChargedBody<B> {
+    fn drop(&mut self) {
+        // A body dropped before it finished still charges for the bytes already transferred
+        let charging = match (self.charging.take(), self.charge.take()) {
+            (Some(charging), _) => charging,
+            (None, Some(charge)) => charge.run(self.downloaded),
+            (None, None) => return,
+        };
+        // The charge is made on a spawned task rather than blocking. If no executor is running on
+        // this thread, as when the body is dropped outside an actix System, the charge is skipped.
+        if TaskExecutor::current().spawn_local(charging).is_err() {
+            trace::charge_skipped();
+        }
+    }
+}
@@ -49,6 +49,43 @@
 //! [`Limiter`]: struct.Limiter.html
 //! [`RateLimiter`]: struct.RateLimiter.html
 //!
+//! ## Limiting Bytes Transferred
+//!
+//! The [`ByteRateLimiter`] middleware enforces a quota of bytes transferred per key rather than a
+//! number of requests. It holds its own [`ByteLimiter`] and charges each key for the bytes it
+//! uploads, downloads, or both once the response has been sent, rejecting requests once the key's
+//! budget is spent. It only requires the `HeaderName` to be present as `Data`:
+//!
+//! ```no_run
+//! use actix_web::{http::header::HeaderName, web, App, HttpResponse};
+//! use limitation_actix_middleware::{ByteLimiter, ByteRateLimiter, Limiter, Transfer};
+//!
+//! let header = web::Data::new(HeaderName::from_static("authorization"));
+//! // Allow 10 MiB of uploads per key per hour (the default period)
+//! let uploads = ByteLimiter::new(
+//!     Limiter::build("redis://127.0.0.1/")
+//!         .limit(10 * 1024 * 1024)
+//!         .finish()?,
+//! );
+//!
+//! let app = App::new()
+//!     .register_data(header.clone())
+//!     .wrap(ByteRateLimiter::new(uploads, Transfer::Upload))
+//!     .service(web::resource("/upload").route(web::post().to(|| HttpResponse::Ok())));
+//! # Ok::<(), limitation_actix_middleware::Error>(())
+//! ```
+//!
+//! [`ByteLimiter`]: struct.ByteLimiter.html
+//! [`ByteRateLimiter`]: struct.ByteRateLimiter.html
+//!
+//! ## Optional Features
+//!
+//! - `tracing`: Records each rate limiting decision with [tracing] spans and events, and enables
+//!   the `tracing` feature of the [limitation] crate. Rate limit keys are not recorded verbatim.
+//!
+//! [limitation]: https://docs.rs/limitation
+//! [tracing]: https://docs.rs/tracing
+//!
 //! # Examples
 //!
 //! This crate ships with an example program called [catchall] which can be run from the sources
@@ -64,9 +101,12 @@
 #![doc(html_root_url = "https://docs.rs/limitation-actix-middleware/0.1.1")]
 #![deny(missing_docs)]
 
+mod byte_rate_limiter;
 mod rate_limiter;
+mod trace;
 
+pub use byte_rate_limiter::{ByteRateLimiter, Transfer};
 pub use rate_limiter::RateLimiter;
 
 // re-export Limitation types
-pub use limitation::{Builder, Error, Limiter, Status};
+pub use limitation::{Builder, ByteLimiter, Error, FailurePolicy, Limiter, Status};
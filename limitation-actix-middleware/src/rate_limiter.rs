@@ -2,6 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::trace;
 use actix_web::{
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
     http::header::HeaderName,
@@ -11,7 +12,7 @@ use futures::{
     future::{self, Either, FutureResult},
     Future, Poll,
 };
-use limitation::{Error as LError, Limiter, Status};
+use limitation::{Error as LError, Limiter, Listing, Status};
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -21,9 +22,15 @@ const HEADER_LIMIT: &str = "x-ratelimit-limit";
 const HEADER_REMAINING: &str = "x-ratelimit-remaining";
 /// The `reset` HTTP header name
 const HEADER_RESET: &str = "x-ratelimit-reset";
+/// The `Retry-After` HTTP header name
+const HEADER_RETRY_AFTER: &str = "retry-after";
 
 /// `Middleware` for rate limiting requests using a fixed window counter keyed on a `HeaaderName`.
 ///
+/// If the Limiter's backend fails, the request is handled according to the Limiter's
+/// [`FailurePolicy`]: it's passed down the chain if the policy is `Open`, and otherwise rejected
+/// with a `503 Service Unavailable` response.
+///
 /// # Example
 ///
 /// A basic example:
@@ -46,6 +53,8 @@ const HEADER_RESET: &str = "x-ratelimit-reset";
 ///     );
 /// # Ok::<(), limitation_actix_middleware::Error>(())
 /// ```
+///
+/// [`FailurePolicy`]: enum.FailurePolicy.html
 pub struct RateLimiter;
 
 impl<S, B> Transform<S> for RateLimiter
@@ -100,37 +109,51 @@ where
         let key = match key(&req) {
             Some(key) => key,
             None => {
+                trace::missing_key(&req);
                 return Box::new(future::ok(
                     req.into_response(HttpResponse::Forbidden().finish().into_body()),
-                ))
+                ));
             }
         };
 
-        Box::new(limiter.count(key).then(move |result| match result {
-            Ok(status) => Either::A(service.borrow_mut().call(req).map(move |mut res| {
-                add_rate_limit_headers(&mut res, &status);
-                res
-            })),
-            Err(LError::LimitExceeded(status)) => Either::B(Either::A(
-                future::ok(req.into_response(HttpResponse::Forbidden().finish().into_body())).map(
-                    move |mut res| {
-                        add_rate_limit_headers(&mut res, &status);
-                        res
-                    },
-                ),
-            )),
-            // TODO: In the case of backend errors this middleware currently continues down the
-            // chain. This may or may not be the desired behavior in the long run and is worthy of
-            // revisiting.
-            Err(_) => Either::B(Either::B(service.borrow_mut().call(req))),
-        }))
+        let span = trace::call_span(&req);
+        let future = limiter.count(key).then(move |result| match result {
+            Ok(status) => {
+                trace::allowed(&status);
+                Either::A(service.borrow_mut().call(req).map(move |mut res| {
+                    add_rate_limit_headers(&mut res, &status);
+                    res
+                }))
+            }
+            Err(LError::LimitExceeded(status)) => {
+                trace::denied(&status);
+                Either::B(Either::A(
+                    future::ok(req.into_response(HttpResponse::Forbidden().finish().into_body()))
+                        .map(move |mut res| {
+                            add_rate_limit_headers(&mut res, &status);
+                            add_retry_after_header(&mut res, &status);
+                            res
+                        }),
+                ))
+            }
+            // A Limiter whose failure policy is open allows requests when its backend fails, so
+            // an error which gets here is turned away
+            Err(err) => {
+                trace::backend_error(&err);
+                Either::B(Either::B(future::ok(req.into_response(
+                    HttpResponse::ServiceUnavailable().finish().into_body(),
+                ))))
+            }
+        });
+
+        Box::new(trace::instrument(future, span))
     }
 }
 
 /// Determines a key on which to rate limit the request.
 ///
 /// If the expected header is not present, then `None` will be returned.
-fn key(req: &ServiceRequest) -> Option<String> {
+pub(crate) fn key(req: &ServiceRequest) -> Option<String> {
     // A mis-configuration of the Actix App will result in a **runtime** failure, so the expect
     // method description is important context for the developer.
     let token_header = req.app_data::<HeaderName>().expect(
@@ -158,3 +181,24 @@ fn add_rate_limit_headers<B>(res: &mut ServiceResponse<B>, status: &Status) {
         status.reset_epoch_utc().into(),
     );
 }
+
+/// Adds a `Retry-After` HTTP header to the outgoing response.
+///
+/// The value is a whole number of seconds, rounded up so that a client honoring the header will
+/// not retry before the next period begins. No header is added for a key on the denylist, as
+/// retrying won't help.
+pub(crate) fn add_retry_after_header<B>(res: &mut ServiceResponse<B>, status: &Status) {
+    if status.listing() == Some(Listing::Denied) {
+        return;
+    }
+
+    let retry_after = status.retry_after();
+    let secs = if retry_after.subsec_nanos() > 0 {
+        retry_after.as_secs() + 1
+    } else {
+        retry_after.as_secs()
+    };
+
+    res.headers_mut()
+        .insert(HeaderName::from_static(HEADER_RETRY_AFTER), secs.into());
+}
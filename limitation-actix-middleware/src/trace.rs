@@ -0,0 +1,113 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Optional `tracing` instrumentation.
+//!
+//! Every function in this module has a no-op counterpart when the `tracing` feature is disabled,
+//! so call sites don't need to be conditionally compiled. Keys are never recorded here; the
+//! `Limiter` records a redacted form of the key in its own span.
+
+#[cfg(feature = "tracing")]
+pub(crate) use self::enabled::*;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use self::disabled::*;
+
+#[cfg(feature = "tracing")]
+mod enabled {
+    use actix_web::dev::ServiceRequest;
+    use futures::Future;
+    use limitation::{Error, Status};
+    use tracing::{debug, debug_span, info, warn, Span};
+    use tracing_futures::Instrument;
+
+    /// Returns a span for the rate limiting decision on a request.
+    pub(crate) fn call_span(req: &ServiceRequest) -> Span {
+        debug_span!("rate_limiter", method = %req.method(), path = req.path())
+    }
+
+    /// Runs a Future within a span.
+    pub(crate) fn instrument<F: Future>(
+        future: F,
+        span: Span,
+    ) -> impl Future<Item = F::Item, Error = F::Error> {
+        future.instrument(span)
+    }
+
+    /// Records that a request was rejected for missing the key header.
+    pub(crate) fn missing_key(req: &ServiceRequest) {
+        info!(
+            method = %req.method(),
+            path = req.path(),
+            "rate limit key header missing, rejecting request"
+        );
+    }
+
+    /// Records that a request was allowed, noting when it was only allowed by a dry run.
+    pub(crate) fn allowed(status: &Status) {
+        if status.would_deny() {
+            info!(
+                limit = status.limit(),
+                retry_after = ?status.retry_after(),
+                "rate limit exceeded in dry run, allowing request"
+            );
+        } else {
+            debug!(remaining = status.remaining(), "request allowed");
+        }
+    }
+
+    /// Records that a request was rejected for exceeding the limit.
+    pub(crate) fn denied(status: &Status) {
+        info!(
+            limit = status.limit(),
+            retry_after = ?status.retry_after(),
+            "rate limit exceeded, rejecting request"
+        );
+    }
+
+    /// Records that the limiter backend failed and the request is rejected as unavailable.
+    pub(crate) fn backend_error(err: &Error) {
+        warn!(error = %err, "rate limiter failed, rejecting request");
+    }
+
+    /// Records that charging a key for the bytes transferred failed once the response was sent.
+    pub(crate) fn charge_failed(err: &Error) {
+        warn!(error = %err, "rate limiter failed to charge for bytes transferred");
+    }
+
+    /// Records that a dropped body wasn't charged, as no executor was running to charge it on.
+    pub(crate) fn charge_skipped() {
+        warn!("no executor running to charge a dropped body, skipping the charge");
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod disabled {
+    use actix_web::dev::ServiceRequest;
+    use futures::Future;
+    use limitation::{Error, Status};
+
+    /// A stand-in for a span when the `tracing` feature is disabled.
+    pub(crate) struct Span;
+
+    pub(crate) fn call_span(_req: &ServiceRequest) -> Span {
+        Span
+    }
+
+    pub(crate) fn instrument<F: Future>(future: F, _span: Span) -> F {
+        future
+    }
+
+    pub(crate) fn missing_key(_req: &ServiceRequest) {}
+
+    pub(crate) fn allowed(_status: &Status) {}
+
+    pub(crate) fn denied(_status: &Status) {}
+
+    pub(crate) fn backend_error(_err: &Error) {}
+
+    pub(crate) fn charge_failed(_err: &Error) {}
+
+    pub(crate) fn charge_skipped() {}
+}
@@ -0,0 +1,153 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use actix_web::{
+    http::{header::HeaderName, StatusCode},
+    test, web, App, Error, HttpResponse,
+};
+use futures::{stream, Future, Stream};
+use limitation::MemoryStore;
+use limitation_actix_middleware::{ByteLimiter, ByteRateLimiter, Limiter, Transfer};
+
+fn limiter(limit: usize) -> ByteLimiter {
+    ByteLimiter::new(
+        Limiter::build_with_store(MemoryStore::new())
+            .limit(limit)
+            .finish()
+            .expect("limiter should build"),
+    )
+}
+
+fn charged(limiter: &ByteLimiter) -> usize {
+    limiter.check("alice").wait().map_or_else(
+        |err| match err {
+            limitation::Error::LimitExceeded(status) => status.count(),
+            other => panic!("expected limit exceeded, got {:?}", other),
+        },
+        |status| status.count(),
+    )
+}
+
+#[test]
+fn chunked_uploads_are_charged_for_bytes_read() {
+    let limiter = limiter(1000);
+    let mut app = test::init_service(
+        App::new()
+            .data(HeaderName::from_static("authorization"))
+            .wrap(ByteRateLimiter::new(limiter.clone(), Transfer::Upload))
+            .route(
+                "/upload",
+                web::post().to(|body: web::Bytes| HttpResponse::Ok().body(body)),
+            ),
+    );
+
+    // The payload is sent without a `Content-Length` header
+    let req = test::TestRequest::post()
+        .uri("/upload")
+        .header("authorization", "alice")
+        .set_payload(vec![0; 1500])
+        .to_request();
+    let res = test::call_service(&mut app, req);
+    assert_eq!(res.status(), StatusCode::OK);
+    test::read_body(res);
+
+    assert_eq!(charged(&limiter), 1500);
+    let req = test::TestRequest::post()
+        .uri("/upload")
+        .header("authorization", "alice")
+        .to_request();
+    assert_eq!(
+        test::call_service(&mut app, req).status(),
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[test]
+fn streamed_downloads_are_charged_for_bytes_written() {
+    let limiter = limiter(1000);
+    let mut app = test::init_service(
+        App::new()
+            .data(HeaderName::from_static("authorization"))
+            .wrap(ByteRateLimiter::new(limiter.clone(), Transfer::Download))
+            .route(
+                "/download",
+                web::get().to(|| {
+                    HttpResponse::Ok().streaming(stream::iter_ok::<_, Error>(vec![
+                        web::Bytes::from(vec![0; 300]),
+                        web::Bytes::from(vec![0; 200]),
+                    ]))
+                }),
+            ),
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/download")
+        .header("authorization", "alice")
+        .to_request();
+    let body = test::read_body(test::call_service(&mut app, req));
+
+    assert_eq!(body.len(), 500);
+    assert_eq!(charged(&limiter), 500);
+}
+
+#[test]
+fn unfinished_downloads_are_charged_for_bytes_written() {
+    let limiter = limiter(1000);
+    let mut app = test::init_service(
+        App::new()
+            .data(HeaderName::from_static("authorization"))
+            .wrap(ByteRateLimiter::new(limiter.clone(), Transfer::Both))
+            .route(
+                "/download",
+                web::get().to(|| {
+                    HttpResponse::Ok().streaming(stream::iter_ok::<_, Error>(vec![
+                        web::Bytes::from(vec![0; 300]),
+                        web::Bytes::from(vec![0; 200]),
+                    ]))
+                }),
+            ),
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/download")
+        .header("authorization", "alice")
+        .to_request();
+    let mut res = test::call_service(&mut app, req);
+    let (_, body) = test::block_on(test::run_on(|| {
+        res.take_body().into_future().map_err(|(err, _)| err)
+    }))
+    .expect("first chunk should be written");
+    // The charge for a dropped body is made on a task spawned on the current thread's executor
+    test::block_on(test::run_on(move || {
+        drop(body);
+        Ok::<_, ()>(())
+    }))
+    .unwrap();
+    test::block_on(test::run_on(|| Ok::<_, ()>(()))).unwrap();
+
+    assert_eq!(charged(&limiter), 300);
+}
+
+#[test]
+fn bodies_dropped_outside_an_executor_are_not_charged() {
+    let limiter = limiter(1000);
+    let mut app = test::init_service(
+        App::new()
+            .data(HeaderName::from_static("authorization"))
+            .wrap(ByteRateLimiter::new(limiter.clone(), Transfer::Download))
+            .route(
+                "/download",
+                web::get().to(|| HttpResponse::Ok().body("hello")),
+            ),
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/download")
+        .header("authorization", "alice")
+        .to_request();
+    let mut res = test::call_service(&mut app, req);
+    drop(res.take_body());
+
+    assert_eq!(charged(&limiter), 0);
+}
@@ -0,0 +1,52 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use actix_web::{
+    http::{header::HeaderName, StatusCode},
+    test, web, App, HttpResponse,
+};
+use limitation_actix_middleware::{FailurePolicy, Limiter, RateLimiter};
+use std::net::TcpListener;
+
+/// Builds a Limiter whose Redis server isn't listening.
+fn unreachable(policy: FailurePolicy) -> Limiter {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("should bind to a free port")
+        .port();
+
+    Limiter::build(&format!("redis://127.0.0.1:{}/", port))
+        .failure_policy(policy)
+        .finish()
+        .expect("limiter should build")
+}
+
+fn status(policy: FailurePolicy) -> StatusCode {
+    let mut app = test::init_service(
+        App::new()
+            .data(HeaderName::from_static("authorization"))
+            .data(unreachable(policy))
+            .wrap(RateLimiter)
+            .route("/", web::get().to(HttpResponse::Ok)),
+    );
+    let req = test::TestRequest::get()
+        .uri("/")
+        .header("authorization", "alice")
+        .to_request();
+
+    test::call_service(&mut app, req).status()
+}
+
+#[test]
+fn backend_failures_are_unavailable() {
+    assert_eq!(
+        status(FailurePolicy::Closed),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+}
+
+#[test]
+fn open_policy_passes_backend_failures() {
+    assert_eq!(status(FailurePolicy::Open), StatusCode::OK);
+}
@@ -5,7 +5,9 @@
 //! Rate-limiting reverse proxy Actix service.
 
 use actix_web::{client::Client, http::header::HeaderName, middleware, web, App, HttpServer};
-use limitation_actix_middleware::{Limiter, RateLimiter};
+use limitation_actix_middleware::{
+    ByteLimiter, ByteRateLimiter, FailurePolicy, Limiter, RateLimiter, Transfer,
+};
 use std::error;
 use std::net::SocketAddr;
 use std::time::Duration;
@@ -13,6 +15,7 @@ use typed_builder::TypedBuilder;
 use url::Url;
 
 mod handlers;
+mod trace;
 
 /// Error type for the application.
 pub type Error = Box<dyn error::Error>;
@@ -26,6 +29,12 @@ pub struct Config {
     pub(crate) header: HeaderName,
     pub(crate) rate_limit: usize,
     pub(crate) rate_period: Duration,
+    #[builder(default)]
+    pub(crate) dry_run: bool,
+    #[builder(default)]
+    pub(crate) upload_limit: Option<usize>,
+    #[builder(default)]
+    pub(crate) download_limit: Option<usize>,
 }
 
 /// Build and run the service given a configuration.
@@ -37,11 +46,18 @@ pub fn run(config: Config) -> Result<(), Error> {
 
 fn start_server(config: Config) -> Result<(), Error> {
     let addr = config.bind_addr;
+    let limit_uploads = config.upload_limit.is_some();
+    let uploads = byte_rate_limiter(&config, config.upload_limit, Transfer::Upload)?;
+    let limit_downloads = config.download_limit.is_some();
+    let downloads = byte_rate_limiter(&config, config.download_limit, Transfer::Download)?;
     let proxy_to = web::Data::new(config.proxy_to);
+    // The proxy keeps forwarding requests if Redis is unavailable
     let limiter = web::Data::new(
         Limiter::build(config.redis_url.as_str())
             .limit(config.rate_limit)
             .period(config.rate_period)
+            .dry_run(config.dry_run)
+            .failure_policy(FailurePolicy::Open)
             .finish()?,
     );
     let header = web::Data::new(config.header);
@@ -53,6 +69,11 @@ fn start_server(config: Config) -> Result<(), Error> {
             .register_data(header.clone())
             .data(Client::new())
             .wrap(RateLimiter)
+            .wrap(middleware::Condition::new(limit_uploads, uploads.clone()))
+            .wrap(middleware::Condition::new(
+                limit_downloads,
+                downloads.clone(),
+            ))
             .wrap(middleware::Logger::default())
             .default_service(web::route().to_async(handlers::forward))
     })
@@ -61,3 +82,29 @@ fn start_server(config: Config) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Builds a middleware enforcing a byte quota for a direction of transfer.
+///
+/// The middleware should only be enabled if a limit is given.
+fn byte_rate_limiter(
+    config: &Config,
+    limit: Option<usize>,
+    transfer: Transfer,
+) -> Result<ByteRateLimiter, Error> {
+    let prefix = match transfer {
+        Transfer::Upload => "limitation:upload",
+        Transfer::Download => "limitation:download",
+        Transfer::Both => "limitation:bytes",
+    };
+    let mut builder = Limiter::build(config.redis_url.as_str());
+    builder
+        .period(config.rate_period)
+        .dry_run(config.dry_run)
+        .failure_policy(FailurePolicy::Open);
+    if let Some(limit) = limit {
+        builder.limit(limit);
+    }
+    let limiter = ByteLimiter::with_prefix(builder.finish()?, prefix);
+
+    Ok(ByteRateLimiter::new(limiter, transfer))
+}
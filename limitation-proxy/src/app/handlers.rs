@@ -12,6 +12,8 @@ use actix_web::{
 use futures::Future;
 use url::Url;
 
+use super::trace;
+
 /// A list of "hop-by-hop" headers that should be removed when transparently proxying traffic.
 ///
 /// For more details, see: https://www.w3.org/Protocols/rfc2616/rfc2616-sec13.html (Section
@@ -34,19 +36,28 @@ pub fn forward(
     proxy_to: web::Data<Url>,
     client: web::Data<Client>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
-    proxied_request(&client, &proxy_to, &req)
+    let proxied_req = proxied_request(&client, &proxy_to, &req);
+    let span = trace::forward_span(&req, proxied_req.get_uri());
+
+    let future = proxied_req
         .send_stream(payload)
         .map_err(Error::from)
-        .map(response)
+        .then(|result| {
+            trace::upstream_result(&result);
+            result
+        })
+        .map(response);
+
+    trace::instrument(future, span)
 }
 
 /// Builds a proxied `ClientRequest` from the incoming `HttpRequest`.
 fn proxied_request(client: &Client, proxy_to: &Url, req: &HttpRequest) -> ClientRequest {
     let mut proxied_req = client
-        .request_from(proxy_url(&proxy_to, req.uri()).as_str(), req.head())
+        .request_from(proxy_url(proxy_to, req.uri()).as_str(), req.head())
         .no_decompress();
     drop_hop_headers_on_request(&mut proxied_req);
-    add_headers_on_request(&mut proxied_req, &req);
+    add_headers_on_request(&mut proxied_req, req);
 
     proxied_req
 }
@@ -0,0 +1,64 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Optional `tracing` instrumentation.
+//!
+//! Every function in this module has a no-op counterpart when the `tracing` feature is disabled,
+//! so call sites don't need to be conditionally compiled.
+
+#[cfg(feature = "tracing")]
+pub(crate) use self::enabled::*;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use self::disabled::*;
+
+#[cfg(feature = "tracing")]
+mod enabled {
+    use actix_http::encoding::Decoder;
+    use actix_web::{client::ClientResponse, dev::Payload, http::Uri, Error, HttpRequest};
+    use futures::Future;
+    use tracing::{debug, debug_span, warn, Span};
+    use tracing_futures::Instrument;
+
+    /// Returns a span for forwarding a request to the proxied backend.
+    pub(crate) fn forward_span(req: &HttpRequest, upstream: &Uri) -> Span {
+        debug_span!("forward", method = %req.method(), path = req.path(), %upstream)
+    }
+
+    /// Runs a Future within a span.
+    pub(crate) fn instrument<F: Future>(
+        future: F,
+        span: Span,
+    ) -> impl Future<Item = F::Item, Error = F::Error> {
+        future.instrument(span)
+    }
+
+    /// Records the response, or failure, from the proxied backend.
+    pub(crate) fn upstream_result(result: &Result<ClientResponse<Decoder<Payload>>, Error>) {
+        match result {
+            Ok(res) => debug!(status = res.status().as_u16(), "proxied backend responded"),
+            Err(err) => warn!(error = %err, "proxied backend request failed"),
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod disabled {
+    use actix_http::encoding::Decoder;
+    use actix_web::{client::ClientResponse, dev::Payload, http::Uri, Error, HttpRequest};
+    use futures::Future;
+
+    /// A stand-in for a span when the `tracing` feature is disabled.
+    pub(crate) struct Span;
+
+    pub(crate) fn forward_span(_req: &HttpRequest, _upstream: &Uri) -> Span {
+        Span
+    }
+
+    pub(crate) fn instrument<F: Future>(future: F, _span: Span) -> F {
+        future
+    }
+
+    pub(crate) fn upstream_result(_result: &Result<ClientResponse<Decoder<Payload>>, Error>) {}
+}
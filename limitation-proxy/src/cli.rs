@@ -61,6 +61,14 @@ pub(crate) struct Args {
     )]
     pub(crate) bind: SocketAddr,
 
+    /// Report requests over the limit without rejecting them
+    #[structopt(long = "dry-run")]
+    pub(crate) dry_run: bool,
+
+    /// Maximum number of response bytes per key in the period
+    #[structopt(long = "download-limit", rename_all = "screaming_snake_case")]
+    pub(crate) download_limit: Option<usize>,
+
     /// Header to be used as the key for rate-limiting
     #[structopt(
         short = "H",
@@ -110,6 +118,10 @@ pub(crate) struct Args {
         default_value = DEFAULT_CLI_REDIS
     )]
     pub(crate) redis: Url,
+
+    /// Maximum number of request bytes per key in the period
+    #[structopt(long = "upload-limit", rename_all = "screaming_snake_case")]
+    pub(crate) upload_limit: Option<usize>,
 }
 
 /// Custom parser that takes a number of seconds as a `str` and returns a `Duration`.
@@ -130,6 +142,9 @@ impl From<Args> for Config {
             .header(args.header)
             .rate_limit(args.limit)
             .rate_period(args.period)
+            .dry_run(args.dry_run)
+            .upload_limit(args.upload_limit)
+            .download_limit(args.download_limit)
             .build()
     }
 }
@@ -0,0 +1,283 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::client::RedisClient;
+use crate::Error;
+use futures::future;
+use futures::Future;
+use std::collections::HashSet;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// A boxed Future returned when checking a key against access lists.
+type CheckFuture<T> = Box<dyn Future<Item = T, Error = Error> + Send>;
+
+/// A list of keys which a [`Limiter`] either always permits or always rejects.
+///
+/// A list can hold exact keys, IP networks in CIDR notation (such as `"10.0.0.0/8"`) which match
+/// any key that is an IP address in the network, and Redis sets which can be changed while a
+/// `Limiter` is running. A Redis set's members are matched in the same way as the static entries,
+/// so a set can hold both keys and networks. Networks in a set must be written with the host bits
+/// cleared, as `"10.0.0.0/8"` rather than `"10.1.2.3/8"`.
+///
+/// An allowlist is given to a `Limiter` with [`Builder::allow`] and a denylist with
+/// [`Builder::deny`].
+///
+/// # Example
+///
+/// ```
+/// use limitation::AccessList;
+///
+/// let mut allow = AccessList::new();
+/// allow.key("health-checker").network("10.0.0.0/8")?;
+/// # Ok::<(), limitation::Error>(())
+/// ```
+///
+/// [`Builder::allow`]: struct.Builder.html#method.allow
+/// [`Builder::deny`]: struct.Builder.html#method.deny
+/// [`Limiter`]: struct.Limiter.html
+#[derive(Clone, Debug, Default)]
+pub struct AccessList {
+    /// Keys which match exactly
+    keys: HashSet<String>,
+    /// Networks which match keys that are IP addresses
+    networks: Vec<Network>,
+    /// Redis sets whose members are matched like `keys` and `networks`
+    sets: Vec<(RedisClient, String)>,
+}
+
+impl AccessList {
+    /// Creates a new, empty `AccessList`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a key which matches exactly.
+    pub fn key<K: Into<String>>(&mut self, key: K) -> &mut Self {
+        self.keys.insert(key.into());
+        self
+    }
+
+    /// Adds an IP network in CIDR notation, such as `"10.0.0.0/8"` or `"2001:db8::/32"`, which
+    /// matches any key that is an IP address in the network.
+    ///
+    /// A plain IP address is also accepted and matches only itself.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the network fails to parse.
+    pub fn network(&mut self, cidr: &str) -> Result<&mut Self, Error> {
+        self.networks.push(Network::parse(cidr)?);
+        Ok(self)
+    }
+
+    /// Adds a Redis set whose members are matched against each key.
+    ///
+    /// The set is read on every check, so members can be added or removed with `SADD` and `SREM`
+    /// at any time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the Redis URL fails to parse.
+    pub fn redis_set<S: Into<String>>(
+        &mut self,
+        redis_url: &str,
+        set: S,
+    ) -> Result<&mut Self, Error> {
+        self.sets.push((RedisClient::open(redis_url)?, set.into()));
+        Ok(self)
+    }
+
+    /// Returns whether a key is on the list.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a client error has occurred while reading a Redis set.
+    pub fn contains(&self, key: &str) -> CheckFuture<bool> {
+        let ip = key.parse::<IpAddr>().ok();
+        if self.keys.contains(key)
+            || ip
+                .into_iter()
+                .any(|ip| self.networks.iter().any(|net| net.contains(ip)))
+        {
+            return Box::new(future::ok(true));
+        }
+        if self.sets.is_empty() {
+            return Box::new(future::ok(false));
+        }
+
+        let mut members = vec![key.to_string()];
+        if let Some(ip) = ip {
+            members.extend(Network::containing(ip).map(|net| net.to_string()));
+        }
+
+        Box::new(
+            future::join_all(
+                self.sets
+                    .iter()
+                    .map(|(client, set)| set_contains_any(client, set.clone(), members.clone()))
+                    .collect::<Vec<_>>(),
+            )
+            .map(|found| found.into_iter().any(|found| found)),
+        )
+    }
+}
+
+/// Whether a key is on a [`Limiter`]'s allowlist or denylist.
+///
+/// A listed key is never counted. The listing is reported by [`Status::listing`].
+///
+/// With the `serde` feature enabled, a `Listing` is serialized as a `snake_case` string.
+///
+/// [`Limiter`]: struct.Limiter.html
+/// [`Status::listing`]: struct.Status.html#method.listing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Listing {
+    /// The key is on the allowlist and is always permitted.
+    Allowed,
+    /// The key is on the denylist and is always rejected.
+    Denied,
+}
+
+/// The allowlist and denylist consulted by a `Limiter` before counting.
+#[derive(Debug)]
+pub(crate) struct AccessLists {
+    /// Keys which are always permitted
+    allow: Option<AccessList>,
+    /// Keys which are always rejected
+    deny: Option<AccessList>,
+}
+
+impl AccessLists {
+    /// Creates a new pair of lists, or `None` if neither is given.
+    pub(crate) fn new(allow: Option<AccessList>, deny: Option<AccessList>) -> Option<Self> {
+        if allow.is_none() && deny.is_none() {
+            None
+        } else {
+            Some(AccessLists { allow, deny })
+        }
+    }
+
+    /// Returns the listing of a key, or `None` if it's on neither list.
+    ///
+    /// A key on both lists is denied.
+    pub(crate) fn check(&self, key: &str) -> CheckFuture<Option<Listing>> {
+        let allowed = contains(self.allow.as_ref(), key);
+        let denied = contains(self.deny.as_ref(), key);
+
+        Box::new(
+            allowed
+                .join(denied)
+                .map(|(allowed, denied)| match (allowed, denied) {
+                    (_, true) => Some(Listing::Denied),
+                    (true, false) => Some(Listing::Allowed),
+                    (false, false) => None,
+                }),
+        )
+    }
+}
+
+/// Returns whether a key is on a list which may not be given.
+fn contains(list: Option<&AccessList>, key: &str) -> CheckFuture<bool> {
+    match list {
+        Some(list) => list.contains(key),
+        None => Box::new(future::ok(false)),
+    }
+}
+
+/// Returns whether any of the given members are in a Redis set, in a single round trip.
+fn set_contains_any(
+    client: &RedisClient,
+    set: String,
+    members: Vec<String>,
+) -> impl Future<Item = bool, Error = Error> {
+    client
+        .get_async_connection()
+        .from_err()
+        .and_then(move |con| {
+            let mut pipe = redis::pipe();
+            for member in &members {
+                pipe.cmd("SISMEMBER").arg(&set).arg(member);
+            }
+
+            pipe.query_async(con)
+                .from_err()
+                .map(|(_, found): (_, Vec<bool>)| found.into_iter().any(|found| found))
+        })
+}
+
+/// An IP network with its host bits cleared.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    /// Parses a network in CIDR notation, or a plain IP address.
+    fn parse(cidr: &str) -> Result<Self, Error> {
+        let invalid = || Error::Config(format!("invalid network: {}", cidr));
+
+        let mut parts = cidr.splitn(2, '/');
+        let addr = parts
+            .next()
+            .and_then(|addr| addr.trim().parse::<IpAddr>().ok())
+            .ok_or_else(invalid)?;
+        let bits = max_prefix(addr);
+        let prefix = match parts.next() {
+            Some(prefix) => prefix.trim().parse::<u8>().map_err(|_| invalid())?,
+            None => bits,
+        };
+        if prefix > bits {
+            return Err(invalid());
+        }
+
+        Ok(Network::new(addr, prefix))
+    }
+
+    /// Creates a network of the given prefix length which contains `addr`.
+    fn new(addr: IpAddr, prefix: u8) -> Self {
+        let addr = match addr {
+            IpAddr::V4(addr) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(addr) & mask))
+            }
+            IpAddr::V6(addr) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(addr) & mask))
+            }
+        };
+
+        Network { addr, prefix }
+    }
+
+    /// Returns every network which contains `addr`, from the largest to the address itself.
+    fn containing(addr: IpAddr) -> impl Iterator<Item = Network> {
+        (0..=max_prefix(addr)).map(move |prefix| Network::new(addr, prefix))
+    }
+
+    /// Returns whether the network contains `addr`.
+    fn contains(&self, addr: IpAddr) -> bool {
+        Network::new(addr, self.prefix) == *self
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Returns the number of bits in an address.
+fn max_prefix(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
@@ -0,0 +1,428 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::client::{self, RedisClient};
+use crate::{epoch_millis, trace, Clock, Error, SystemClock};
+use futures::future::{self, Either};
+use futures::Future;
+use redis::aio::Connection;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// The default lowest limit
+const DEFAULT_MIN: usize = 10;
+/// The default highest limit
+const DEFAULT_MAX: usize = 5000;
+/// The default amount the limit is raised by for each healthy response
+const DEFAULT_INCREASE: usize = 1;
+/// The default factor the limit is multiplied by when the backend is struggling
+const DEFAULT_DECREASE: f64 = 0.5;
+/// The default time after a decrease during which further decreases are ignored, in seconds
+const DEFAULT_COOLDOWN_SECS: u64 = 5;
+/// The default time a limit read from Redis is reused before it's read again, in seconds
+const DEFAULT_REFRESH_SECS: u64 = 1;
+/// The default Redis key holding the shared limit
+const DEFAULT_KEY: &str = "limitation:adaptive";
+/// Raises or lowers the shared limit, unless a decrease falls within the cooldown.
+///
+/// `KEYS[1]` is the hash holding the limit, along with when it was last decreased (in milliseconds
+/// since the epoch) in its `decreased_at` field. The arguments are whether the backend is
+/// congested (`1` or `0`), the current time and the cooldown (both in milliseconds), the initial
+/// limit, the minimum, the maximum, the increase, and the decrease factor. Returns the limit before
+/// and after the adjustment.
+const ADJUST_SCRIPT: &str = r"
+local function clamp(limit)
+    return math.max(tonumber(ARGV[5]), math.min(tonumber(ARGV[6]), limit))
+end
+
+local stored = redis.call('HGET', KEYS[1], 'limit')
+local current = clamp(tonumber(stored or ARGV[4]))
+local decreased_at = redis.call('HGET', KEYS[1], 'decreased_at')
+local now = tonumber(ARGV[2])
+local cooling = decreased_at and now < tonumber(decreased_at) + tonumber(ARGV[3])
+local adjusted = current
+
+if ARGV[1] == '0' then
+    adjusted = clamp(current + tonumber(ARGV[7]))
+elseif not cooling then
+    adjusted = clamp(math.floor(current * tonumber(ARGV[8])))
+    redis.call('HSET', KEYS[1], 'decreased_at', ARGV[2])
+end
+
+if adjusted ~= current or not stored then
+    redis.call('HSET', KEYS[1], 'limit', adjusted)
+end
+return {current, adjusted}
+";
+
+/// The hash field holding the current limit
+const FIELD_LIMIT: &str = "limit";
+
+/// A limit which adapts to the health of a backend, shared across processes through Redis.
+///
+/// Rather than a fixed number of requests per period, an `AdaptiveLimit` follows an additive
+/// increase, multiplicative decrease (AIMD) scheme. Each response from the backend is reported
+/// with [`report`]: a healthy response raises the limit by a fixed step, while an error or a
+/// response slower than the latency target multiplies the limit by a factor less than one. The
+/// limit always stays between a minimum and a maximum. After a decrease, further decreases are
+/// ignored for a cooldown period so that a burst of errors caused by one incident only lowers the
+/// limit once.
+///
+/// The limit is kept in a Redis hash and adjusted by a Lua script, which the server runs
+/// atomically, so every process sharing the Redis server adapts the same limit. A [`Limiter`] is
+/// given an `AdaptiveLimit` with [`Builder::adaptive`], and uses its current value in place of a
+/// fixed limit. The current value is cached and read from Redis again at most once per refresh
+/// interval.
+///
+/// Clones of an `AdaptiveLimit` share their cached value, so a clone given to a `Limiter` sees the
+/// adjustments reported to the original straight away.
+///
+/// # Example
+///
+/// ```no_run
+/// use futures::Future;
+/// use limitation::{AdaptiveLimit, Feedback, Limiter};
+/// use std::time::Duration;
+///
+/// let adaptive = AdaptiveLimit::build("redis://127.0.0.1/")
+///     .min(100)
+///     .max(5000)
+///     .latency_target(Duration::from_millis(250))
+///     .finish()?;
+/// let limiter = Limiter::build("redis://127.0.0.1/")
+///     .adaptive(adaptive.clone())
+///     .finish()?;
+///
+/// // ... after proxying a request to the backend ...
+/// let limit = adaptive
+///     .report(Feedback::Success(Duration::from_millis(120)))
+///     .wait()?;
+/// println!("the limit is now {}", limit);
+/// # Ok::<(), limitation::Error>(())
+/// ```
+///
+/// [`Builder::adaptive`]: struct.Builder.html#method.adaptive
+/// [`Limiter`]: struct.Limiter.html
+/// [`report`]: #method.report
+#[derive(Clone, Debug)]
+pub struct AdaptiveLimit {
+    /// The Redis client
+    client: RedisClient,
+    /// The source of the current time
+    clock: Arc<dyn Clock>,
+    /// The Redis key holding the shared limit
+    key: String,
+    /// The lowest limit
+    min: usize,
+    /// The highest limit
+    max: usize,
+    /// The limit used before any feedback has been reported
+    initial: usize,
+    /// The amount the limit is raised by for each healthy response
+    increase: usize,
+    /// The factor the limit is multiplied by when the backend is struggling
+    decrease: f64,
+    /// The latency above which a successful response counts as the backend struggling, if any
+    latency_target: Option<Duration>,
+    /// The time after a decrease during which further decreases are ignored
+    cooldown: Duration,
+    /// The time a limit read from Redis is reused before it's read again
+    refresh: Duration,
+    /// The last known limit and when it was read
+    cached: Arc<Mutex<Option<(usize, SystemTime)>>>,
+}
+
+impl AdaptiveLimit {
+    /// Returns a builder for an `AdaptiveLimit`.
+    pub fn build(redis_url: &str) -> AdaptiveBuilder<'_> {
+        AdaptiveBuilder::new(redis_url)
+    }
+
+    /// Returns the current limit.
+    ///
+    /// The limit is read from Redis unless it was read or adjusted within the refresh interval.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a client error has occurred.
+    pub fn current(&self) -> impl Future<Item = usize, Error = Error> {
+        let now = self.clock.now();
+        if let Some(limit) = self.fresh(now) {
+            return Either::A(future::ok(limit));
+        }
+
+        let limit = self.clone();
+        Either::B(
+            self.client
+                .get_async_connection()
+                .from_err()
+                .and_then(move |con| {
+                    redis::cmd("HGET")
+                        .arg(&limit.key)
+                        .arg(FIELD_LIMIT)
+                        .query_async(con)
+                        .from_err()
+                        .map(move |(_, stored): (_, Option<usize>)| {
+                            let current = limit.clamp(stored.unwrap_or(limit.initial));
+                            limit.remember(current, now);
+                            current
+                        })
+                }),
+        )
+    }
+
+    /// Reports the outcome of a request to the backend and returns the adjusted limit.
+    ///
+    /// A [`Feedback::Success`] within the latency target raises the limit, while a
+    /// [`Feedback::Failure`] or a success slower than the latency target lowers it, unless the
+    /// limit was lowered within the cooldown period. Feedback reported by several processes at
+    /// once is applied in turn, so none of it is lost.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a client error has occurred.
+    ///
+    /// [`Feedback::Failure`]: enum.Feedback.html#variant.Failure
+    /// [`Feedback::Success`]: enum.Feedback.html#variant.Success
+    pub fn report(&self, feedback: Feedback) -> impl Future<Item = usize, Error = Error> {
+        let congested = match feedback {
+            Feedback::Success(latency) => self
+                .latency_target
+                .into_iter()
+                .any(|target| latency > target),
+            Feedback::Failure => true,
+        };
+        let now = self.clock.now();
+        let limit = self.clone();
+
+        self.client
+            .get_async_connection()
+            .from_err()
+            .and_then(move |con| limit.adjust(con, congested, now))
+    }
+
+    /// Returns the last known limit without reading it from Redis.
+    pub(crate) fn last(&self) -> usize {
+        self.cached
+            .lock()
+            .expect("lock should not be poisoned")
+            .map_or(self.initial, |(limit, _)| limit)
+    }
+
+    /// Adjusts the shared limit with a script, which the server runs atomically, and caches the
+    /// adjusted limit.
+    fn adjust(
+        &self,
+        con: Connection,
+        congested: bool,
+        now: SystemTime,
+    ) -> impl Future<Item = usize, Error = Error> {
+        let limit = self.clone();
+
+        client::eval(ADJUST_SCRIPT, 1)
+            .arg(&self.key)
+            .arg(if congested { 1 } else { 0 })
+            .arg(epoch_millis(now))
+            .arg(self.cooldown.as_millis() as u64)
+            .arg(self.initial)
+            .arg(self.min)
+            .arg(self.max)
+            .arg(self.increase)
+            .arg(self.decrease.to_string())
+            .query_async(con)
+            .from_err()
+            .map(move |(_, (current, adjusted)): (_, (usize, usize))| {
+                if adjusted != current {
+                    trace::limit_adjusted(current, adjusted);
+                }
+                limit.remember(adjusted, now);
+                adjusted
+            })
+    }
+
+    /// Returns the cached limit if it was read within the refresh interval.
+    fn fresh(&self, now: SystemTime) -> Option<usize> {
+        let refresh = self.refresh;
+
+        self.cached
+            .lock()
+            .expect("lock should not be poisoned")
+            .filter(|(_, read_at)| {
+                now.duration_since(*read_at)
+                    .map(|age| age < refresh)
+                    .unwrap_or(false)
+            })
+            .map(|(limit, _)| limit)
+    }
+
+    /// Caches a limit read or adjusted at the given time.
+    fn remember(&self, limit: usize, now: SystemTime) {
+        *self.cached.lock().expect("lock should not be poisoned") = Some((limit, now));
+    }
+
+    /// Keeps a limit between the minimum and maximum.
+    fn clamp(&self, limit: usize) -> usize {
+        limit.max(self.min).min(self.max)
+    }
+}
+
+/// The outcome of a request to a backend, reported to an [`AdaptiveLimit`].
+///
+/// [`AdaptiveLimit`]: struct.AdaptiveLimit.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feedback {
+    /// The backend handled the request successfully, taking the given time.
+    Success(Duration),
+    /// The backend failed to handle the request, was overloaded, or timed out.
+    Failure,
+}
+
+/// A builder for an [`AdaptiveLimit`].
+///
+/// [`AdaptiveLimit`]: struct.AdaptiveLimit.html
+pub struct AdaptiveBuilder<'a> {
+    redis_url: &'a str,
+    clock: Arc<dyn Clock>,
+    key: String,
+    min: usize,
+    max: usize,
+    initial: Option<usize>,
+    increase: usize,
+    decrease: f64,
+    latency_target: Option<Duration>,
+    cooldown: Duration,
+    refresh: Duration,
+}
+
+impl<'a> AdaptiveBuilder<'a> {
+    /// Creates a new `AdaptiveBuilder` with default settings.
+    fn new(redis_url: &'a str) -> Self {
+        AdaptiveBuilder {
+            redis_url,
+            clock: Arc::new(SystemClock),
+            key: DEFAULT_KEY.to_string(),
+            min: DEFAULT_MIN,
+            max: DEFAULT_MAX,
+            initial: None,
+            increase: DEFAULT_INCREASE,
+            decrease: DEFAULT_DECREASE,
+            latency_target: None,
+            cooldown: Duration::from_secs(DEFAULT_COOLDOWN_SECS),
+            refresh: Duration::from_secs(DEFAULT_REFRESH_SECS),
+        }
+    }
+
+    /// Sets the lowest the limit can fall to. The default is `10`.
+    pub fn min(&mut self, min: usize) -> &mut Self {
+        self.min = min;
+        self
+    }
+
+    /// Sets the highest the limit can rise to. The default is `5000`.
+    pub fn max(&mut self, max: usize) -> &mut Self {
+        self.max = max;
+        self
+    }
+
+    /// Sets the limit used before any feedback has been reported. The default is the maximum.
+    pub fn initial(&mut self, initial: usize) -> &mut Self {
+        self.initial = Some(initial);
+        self
+    }
+
+    /// Sets the amount the limit is raised by for each healthy response. The default is `1`.
+    pub fn increase(&mut self, increase: usize) -> &mut Self {
+        self.increase = increase;
+        self
+    }
+
+    /// Sets the factor the limit is multiplied by when the backend is struggling, which must be
+    /// greater than `0.0` and less than `1.0`. The default is `0.5`.
+    pub fn decrease(&mut self, decrease: f64) -> &mut Self {
+        self.decrease = decrease;
+        self
+    }
+
+    /// Sets the latency above which a successful response counts as the backend struggling.
+    ///
+    /// By default only failures lower the limit.
+    pub fn latency_target(&mut self, latency_target: Duration) -> &mut Self {
+        self.latency_target = Some(latency_target);
+        self
+    }
+
+    /// Sets the time after a decrease during which further decreases are ignored. The default is
+    /// 5 seconds.
+    ///
+    /// The cooldown should be about as long as it takes a lower limit to relieve the backend.
+    pub fn cooldown(&mut self, cooldown: Duration) -> &mut Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Sets how long a limit read from Redis is reused before it's read again. The default is 1
+    /// second.
+    pub fn refresh(&mut self, refresh: Duration) -> &mut Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Sets a new clock for the AdaptiveLimit. The default is a [`SystemClock`].
+    ///
+    /// The cooldown is computed from this clock, so every process sharing a Redis server should
+    /// have closely synchronized clocks.
+    ///
+    /// [`SystemClock`]: struct.SystemClock.html
+    pub fn clock<C: Clock + 'static>(&mut self, clock: C) -> &mut Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets the Redis key holding the shared limit. The default is `"limitation:adaptive"`.
+    ///
+    /// AdaptiveLimits for different backends must use different keys.
+    pub fn key<K: Into<String>>(&mut self, key: K) -> &mut Self {
+        self.key = key.into();
+        self
+    }
+
+    /// Finializes and returns an `AdaptiveLimit`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if:
+    ///
+    /// - The Redis URL fails to parse
+    /// - The minimum is greater than the maximum
+    /// - The decrease factor isn't between `0.0` and `1.0`
+    pub fn finish(&self) -> Result<AdaptiveLimit, Error> {
+        if self.min > self.max {
+            return Err(Error::Config(format!(
+                "adaptive minimum {} is greater than maximum {}",
+                self.min, self.max
+            )));
+        }
+        if !(self.decrease > 0.0 && self.decrease < 1.0) {
+            return Err(Error::Config(format!(
+                "adaptive decrease {} must be between 0.0 and 1.0",
+                self.decrease
+            )));
+        }
+
+        Ok(AdaptiveLimit {
+            client: RedisClient::open(self.redis_url)?,
+            clock: self.clock.clone(),
+            key: self.key.clone(),
+            min: self.min,
+            max: self.max,
+            initial: self.initial.unwrap_or(self.max).max(self.min).min(self.max),
+            increase: self.increase,
+            decrease: self.decrease,
+            latency_target: self.latency_target,
+            cooldown: self.cooldown,
+            refresh: self.refresh,
+            cached: Arc::new(Mutex::new(None)),
+        })
+    }
+}
@@ -0,0 +1,158 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{trace, window_status, Error, Limiter, Status};
+use futures::Future;
+
+/// The default prefix for the store keys holding each key's byte budget
+const DEFAULT_PREFIX: &str = "limitation:bytes";
+
+/// A limiter which caps the number of bytes transferred on a key in a period.
+///
+/// A `ByteLimiter` wraps a [`Limiter`] whose limit is a budget of bytes per period rather than a
+/// number of requests. The size of a transfer often isn't known until it's finished, so a
+/// transfer is first checked with [`check`], which only rejects it once the budget is spent, and
+/// then charged for its size with [`charge`] when the size is known. A transfer which is allowed
+/// can therefore take a key over its budget, in which case the key's next transfer is rejected
+/// until the window resets. Where the size is known up front, [`Limiter::count_by`] charges and
+/// checks in one step.
+///
+/// The Limiter's store, clock, period, window, and observers are used, and checks are reported to
+/// observers as decisions. Budgets are kept among the Limiter's own store keys, apart from the
+/// keys requests are counted on, so that they never share counters with requests however a key
+/// is crafted. Allowlists, denylists, penalties, leases, priority reserves, and adaptive limits
+/// don't apply to byte budgets.
+///
+/// # Example
+///
+/// ```no_run
+/// use futures::Future;
+/// use limitation::{ByteLimiter, Limiter};
+/// use std::time::Duration;
+///
+/// // 100 MiB per hour
+/// let limiter = ByteLimiter::new(
+///     Limiter::build("redis://127.0.0.1/")
+///         .limit(100 * 1024 * 1024)
+///         .period(Duration::from_secs(60 * 60))
+///         .finish()?,
+/// );
+///
+/// limiter.check("alice").wait()?;
+/// // ... send the response, and then charge for its size ...
+/// let status = limiter.charge("alice", 4096).wait()?;
+/// println!("bytes remaining: {}", status.remaining());
+/// # Ok::<(), limitation::Error>(())
+/// ```
+///
+/// [`charge`]: #method.charge
+/// [`check`]: #method.check
+/// [`Limiter`]: struct.Limiter.html
+/// [`Limiter::count_by`]: struct.Limiter.html#method.count_by
+#[derive(Clone, Debug)]
+pub struct ByteLimiter {
+    /// The Limiter whose limit is a number of bytes
+    limiter: Limiter,
+    /// The prefix for store keys
+    prefix: String,
+}
+
+impl ByteLimiter {
+    /// Creates a new `ByteLimiter` from a Limiter whose limit is a number of bytes per period.
+    ///
+    /// Budgets are kept under the prefix `"limitation:bytes"`.
+    pub fn new(limiter: Limiter) -> Self {
+        Self::with_prefix(limiter, DEFAULT_PREFIX)
+    }
+
+    /// Creates a new `ByteLimiter` which keeps its budgets under the given prefix.
+    ///
+    /// ByteLimiters for different budgets, such as separate upload and download quotas, must use
+    /// different prefixes.
+    pub fn with_prefix<P: Into<String>>(limiter: Limiter, prefix: P) -> Self {
+        ByteLimiter {
+            limiter,
+            prefix: prefix.into(),
+        }
+    }
+
+    /// Checks that a key has some of its budget left, without charging it, and returns a
+    /// [`Status`] in which the limit and counts are numbers of bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if:
+    ///
+    /// - The budget has been spent in the current period
+    /// - A client error has occurred
+    /// - A time computation failed
+    ///
+    /// [`Status`]: struct.Status.html
+    pub fn check<K: Into<String>>(&self, key: K) -> impl Future<Item = Status, Error = Error> {
+        let key = key.into();
+        let limiter = &self.limiter;
+        let limit = limiter.limit;
+        let period = limiter.period;
+        let now = limiter.clock.now();
+        let bounds = limiter.window.bounds(limiter.timezone, now);
+        let (store_key, _) = limiter.reserved_window_key(&self.budget_key(&key), now, bounds);
+        let span = trace::count_span(&key, limiter.trace_keys, limit);
+
+        let future = limiter.store.peek(store_key, now).and_then(move |current| {
+            let (count, ttl) = current.unwrap_or((0, period));
+            let status = window_status(count, limit, period, now, bounds, ttl)?;
+
+            if status.remaining() > 0 {
+                Ok(status)
+            } else {
+                Err(Error::LimitExceeded(status))
+            }
+        });
+
+        trace::instrument(limiter.decide(key, limit, future), span)
+    }
+
+    /// Charges a key for a transfer of `bytes` and returns a [`Status`] in which the limit and
+    /// counts are numbers of bytes.
+    ///
+    /// The transfer has already happened, so a charge which takes the key over its budget isn't
+    /// an error. The returned `Status` reports no bytes remaining and the next [`check`] on the
+    /// key is rejected.
+    ///
+    /// A charge isn't a decision, so unlike a [`check`] it isn't reported to observers or affected
+    /// by dry run mode. As with checks, the allowlist and denylist aren't consulted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if:
+    ///
+    /// - A client error has occurred
+    /// - A time computation failed
+    ///
+    /// [`check`]: #method.check
+    /// [`Status`]: struct.Status.html
+    pub fn charge<K: Into<String>>(
+        &self,
+        key: K,
+        bytes: usize,
+    ) -> impl Future<Item = Status, Error = Error> {
+        let limiter = &self.limiter;
+        let limit = limiter.limit;
+        let period = limiter.period;
+        let now = limiter.clock.now();
+        let bounds = limiter.window.bounds(limiter.timezone, now);
+        let (store_key, store_period) =
+            limiter.reserved_window_key(&self.budget_key(&key.into()), now, bounds);
+
+        limiter
+            .store
+            .track_by(store_key, bytes, store_period, now)
+            .and_then(move |(count, ttl)| window_status(count, limit, period, now, bounds, ttl))
+    }
+
+    /// Returns the name of a key's byte budget, which is kept among the Limiter's own keys.
+    fn budget_key(&self, key: &str) -> String {
+        format!("{}:{}", self.prefix, key)
+    }
+}
@@ -0,0 +1,94 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A source of the current time.
+///
+/// A [`Limiter`] consults its clock whenever it needs to know "now", for example when computing
+/// the time at which a period resets. The default is a [`SystemClock`], but a [`ManualClock`] can
+/// be used to control time explicitly.
+///
+/// [`Limiter`]: struct.Limiter.html
+/// [`ManualClock`]: struct.ManualClock.html
+/// [`SystemClock`]: struct.SystemClock.html
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// A `Clock` which reports the operating system's current time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A `Clock` which only moves when told to.
+///
+/// Clones of a `ManualClock` share the same underlying time, so a clone can be handed to a
+/// [`Limiter`] while the original is advanced by the caller. This is primarily useful for testing
+/// window boundaries without waiting on real time to pass.
+///
+/// # Example
+///
+/// ```
+/// use limitation::{Clock, ManualClock};
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::from_epoch_secs(1_000);
+/// clock.advance(Duration::from_secs(30));
+///
+/// assert_eq!(clock.epoch_secs(), 1_030);
+/// ```
+///
+/// [`Limiter`]: struct.Limiter.html
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl ManualClock {
+    /// Creates a new `ManualClock` starting at the given time.
+    pub fn new(now: SystemTime) -> Self {
+        ManualClock {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Creates a new `ManualClock` starting at a UNIX timestamp in seconds.
+    pub fn from_epoch_secs(secs: u64) -> Self {
+        Self::new(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    /// Moves the clock forward by the given duration.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("clock lock was poisoned");
+        *now += duration;
+    }
+
+    /// Sets the clock to the given time.
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().expect("clock lock was poisoned") = now;
+    }
+
+    /// Returns the current time of the clock as a UNIX timestamp in seconds.
+    pub fn epoch_secs(&self) -> u64 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().expect("clock lock was poisoned")
+    }
+}
@@ -0,0 +1,460 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::client::{self, RedisClient};
+use crate::{epoch_millis, epoch_nanos, Clock, Error, SystemClock};
+use futures::Future;
+use redis::aio::Connection;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio_executor::{DefaultExecutor, Executor};
+
+/// The default maximum number of permits held at once for a key
+const DEFAULT_MAX: usize = 10;
+/// The default time a permit is held before it expires, in seconds
+const DEFAULT_LEASE_SECS: u64 = 60;
+/// The default prefix for the Redis keys holding each key's permits
+const DEFAULT_PREFIX: &str = "limitation:concurrency";
+/// Takes a permit if fewer than the maximum are held, after reclaiming expired permits.
+///
+/// `KEYS[1]` is the set of permits, and the arguments are the current time, the maximum, the new
+/// permit's expiry and lease (all in milliseconds), and its id. Returns whether the permit was
+/// taken, and the number of permits held including it.
+const TAKE_SCRIPT: &str = r"
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])
+local held = redis.call('ZCARD', KEYS[1])
+if held >= tonumber(ARGV[2]) then
+    return {0, held}
+end
+redis.call('ZADD', KEYS[1], ARGV[3], ARGV[5])
+redis.call('PEXPIRE', KEYS[1], ARGV[4])
+return {1, held + 1}
+";
+
+/// A limiter which caps the number of requests in flight at once for a key, backed by Redis.
+///
+/// Where a [`Limiter`] counts requests in a period, a `ConcurrencyLimiter` acts as a distributed
+/// semaphore: each request holds a [`Permit`] while it is in flight, and no more than the
+/// maximum number of permits can be held for a key at once across every process sharing the
+/// Redis server. A permit is given back when it is dropped.
+///
+/// The permits for a key are held in a Redis sorted set, scored by when each permit's lease
+/// expires. A permit whose holder crashed without giving it back is reclaimed once its lease
+/// expires, so the lease should be longer than any request is expected to take. A permit for a
+/// longer running task can be kept alive with [`Permit::renew`].
+///
+/// # Example
+///
+/// ```no_run
+/// use futures::Future;
+/// use limitation::ConcurrencyLimiter;
+/// use std::time::Duration;
+///
+/// let limiter = ConcurrencyLimiter::build("redis://127.0.0.1/")
+///     .max(2)
+///     .lease(Duration::from_secs(30))
+///     .finish()?;
+///
+/// tokio::run(
+///     limiter
+///         .try_acquire("alice")
+///         .and_then(|permit| {
+///             println!("in flight: {}", permit.status().in_flight());
+///             // ... handle the request while holding the permit ...
+///             permit.release()
+///         })
+///         .map_err(|err| eprintln!("err: {}", err)),
+/// );
+/// # Ok::<(), limitation::Error>(())
+/// ```
+///
+/// [`Limiter`]: struct.Limiter.html
+/// [`Permit`]: struct.Permit.html
+/// [`Permit::renew`]: struct.Permit.html#method.renew
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimiter {
+    /// The Redis client
+    client: RedisClient,
+    /// The source of the current time
+    clock: Arc<dyn Clock>,
+    /// The prefix for Redis keys
+    prefix: String,
+    /// The maximum number of permits held at once for a key
+    max: usize,
+    /// How long a permit is held before it expires
+    lease: Duration,
+}
+
+impl ConcurrencyLimiter {
+    /// Returns a builder for a `ConcurrencyLimiter`.
+    pub fn build(redis_url: &str) -> ConcurrencyBuilder<'_> {
+        ConcurrencyBuilder::new(redis_url)
+    }
+
+    /// Attempts to take a [`Permit`] on a key without waiting.
+    ///
+    /// The held permits are counted and the new permit added by a single Lua script, which Redis
+    /// runs atomically, so nothing is added for a rejected request and a request is only turned
+    /// away when every permit is held. Expired permits are reclaimed before the new permit is
+    /// counted. If the maximum number of permits is already held, an
+    /// `Error::ConcurrencyExceeded` containing a [`ConcurrencyStatus`] is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if:
+    ///
+    /// - The maximum number of permits is already held for the key
+    /// - A client error has occurred
+    ///
+    /// [`ConcurrencyStatus`]: struct.ConcurrencyStatus.html
+    /// [`Permit`]: struct.Permit.html
+    pub fn try_acquire<K: Into<String>>(
+        &self,
+        key: K,
+    ) -> impl Future<Item = Permit, Error = Error> {
+        let key = key.into();
+        let set = self.set_key(&key);
+        let id = permit_id();
+        let max = self.max;
+        let lease = self.lease;
+        let now = epoch_millis(self.clock.now());
+        let client = self.client.clone();
+        let clock = self.clock.clone();
+
+        self.client
+            .get_async_connection()
+            .from_err()
+            .and_then({
+                let set = set.clone();
+                let id = id.clone();
+                move |con| take(con, &set, &id, max, lease, now)
+            })
+            .and_then(move |(taken, in_flight)| {
+                let status = ConcurrencyStatus { max, in_flight };
+
+                if taken {
+                    Ok(Permit {
+                        client,
+                        clock,
+                        set,
+                        id,
+                        lease,
+                        status,
+                        released: false,
+                    })
+                } else {
+                    Err(Error::ConcurrencyExceeded(status))
+                }
+            })
+    }
+
+    /// Returns a [`ConcurrencyStatus`] reporting how many permits are currently held on a key.
+    ///
+    /// Expired permits are reclaimed before they are counted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a client error has occurred.
+    ///
+    /// [`ConcurrencyStatus`]: struct.ConcurrencyStatus.html
+    pub fn status<K: Into<String>>(
+        &self,
+        key: K,
+    ) -> impl Future<Item = ConcurrencyStatus, Error = Error> {
+        let set = self.set_key(&key.into());
+        let max = self.max;
+        let now = epoch_millis(self.clock.now());
+
+        self.client
+            .get_async_connection()
+            .from_err()
+            .and_then(move |con| {
+                let mut pipe = redis::pipe();
+                pipe.atomic()
+                    .cmd("ZREMRANGEBYSCORE")
+                    .arg(&set)
+                    .arg("-inf")
+                    .arg(now)
+                    .ignore()
+                    .cmd("ZCARD")
+                    .arg(&set);
+
+                pipe.query_async(con).from_err()
+            })
+            .map(move |(_, (in_flight,)): (_, (usize,))| ConcurrencyStatus { max, in_flight })
+    }
+
+    /// Returns the Redis key of the sorted set holding a key's permits.
+    fn set_key(&self, key: &str) -> String {
+        format!("{}:{}", self.prefix, key)
+    }
+}
+
+/// A builder for a [`ConcurrencyLimiter`].
+///
+/// [`ConcurrencyLimiter`]: struct.ConcurrencyLimiter.html
+pub struct ConcurrencyBuilder<'a> {
+    redis_url: &'a str,
+    clock: Arc<dyn Clock>,
+    prefix: String,
+    max: usize,
+    lease: Duration,
+}
+
+impl<'a> ConcurrencyBuilder<'a> {
+    /// Creates a new `ConcurrencyBuilder` with default settings.
+    fn new(redis_url: &'a str) -> Self {
+        ConcurrencyBuilder {
+            redis_url,
+            clock: Arc::new(SystemClock),
+            prefix: DEFAULT_PREFIX.to_string(),
+            max: DEFAULT_MAX,
+            lease: Duration::from_secs(DEFAULT_LEASE_SECS),
+        }
+    }
+
+    /// Sets the maximum number of permits which can be held at once for a key. The default is
+    /// `10`.
+    pub fn max(&mut self, max: usize) -> &mut Self {
+        self.max = max;
+        self
+    }
+
+    /// Sets how long a permit is held before it expires. The default is 60 seconds.
+    ///
+    /// A permit is normally given back when it is released or dropped, so the lease only matters
+    /// when its holder crashes, loses its connection to Redis, or drops it outside of a Tokio
+    /// runtime.
+    pub fn lease(&mut self, lease: Duration) -> &mut Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Sets a new clock for the ConcurrencyLimiter. The default is a [`SystemClock`].
+    ///
+    /// Permits expire at a time computed from this clock, so every process sharing a Redis
+    /// server should have closely synchronized clocks.
+    ///
+    /// [`SystemClock`]: struct.SystemClock.html
+    pub fn clock<C: Clock + 'static>(&mut self, clock: C) -> &mut Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets the prefix of the Redis keys holding each key's permits.
+    ///
+    /// The default is `"limitation:concurrency"`. ConcurrencyLimiters with different maximums
+    /// must use different prefixes.
+    pub fn prefix<P: Into<String>>(&mut self, prefix: P) -> &mut Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Finializes and returns a `ConcurrencyLimiter`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the Redis URL fails to parse.
+    pub fn finish(&self) -> Result<ConcurrencyLimiter, Error> {
+        Ok(ConcurrencyLimiter {
+            client: RedisClient::open(self.redis_url)?,
+            clock: self.clock.clone(),
+            prefix: self.prefix.clone(),
+            max: self.max,
+            lease: self.lease,
+        })
+    }
+}
+
+/// A permit to have one request in flight for a key, taken from a [`ConcurrencyLimiter`].
+///
+/// The permit is given back when it is dropped, by removing it from Redis on a task spawned on
+/// the current Tokio executor. A permit dropped where no executor is running is only reclaimed
+/// once its lease expires, so outside of a runtime use [`release`] to give the permit back and
+/// wait until Redis has been updated.
+///
+/// [`ConcurrencyLimiter`]: struct.ConcurrencyLimiter.html
+/// [`release`]: #method.release
+#[derive(Debug)]
+pub struct Permit {
+    /// The Redis client
+    client: RedisClient,
+    /// The source of the current time
+    clock: Arc<dyn Clock>,
+    /// The Redis key of the sorted set holding the permit
+    set: String,
+    /// The unique member of the sorted set for this permit
+    id: String,
+    /// How long the permit is held before it expires
+    lease: Duration,
+    /// The status of the key when the permit was taken
+    status: ConcurrencyStatus,
+    /// Whether the permit has already been given back
+    released: bool,
+}
+
+impl Permit {
+    /// Returns the status of the key when the permit was taken, including this permit.
+    pub fn status(&self) -> &ConcurrencyStatus {
+        &self.status
+    }
+
+    /// Extends the permit's lease to a full lease from now, returning `false` if the permit had
+    /// already expired and been reclaimed.
+    ///
+    /// An expired permit is not renewed, as its place may have been taken by another request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a client error has occurred.
+    pub fn renew(&self) -> impl Future<Item = bool, Error = Error> {
+        let set = self.set.clone();
+        let id = self.id.clone();
+        let lease = self.lease;
+        let now = epoch_millis(self.clock.now());
+
+        self.client
+            .get_async_connection()
+            .from_err()
+            .and_then(move |con| {
+                let mut pipe = redis::pipe();
+                pipe.atomic()
+                    .cmd("ZREMRANGEBYSCORE")
+                    .arg(&set)
+                    .arg("-inf")
+                    .arg(now)
+                    .ignore()
+                    .cmd("ZADD")
+                    .arg(&set)
+                    .arg("XX")
+                    .arg(now + lease.as_millis() as u64)
+                    .arg(&id)
+                    .ignore()
+                    .cmd("ZSCORE")
+                    .arg(&set)
+                    .arg(&id)
+                    .cmd("PEXPIRE")
+                    .arg(&set)
+                    .arg(lease.as_millis() as u64)
+                    .ignore();
+
+                pipe.query_async(con).from_err()
+            })
+            .map(|(_, (score,)): (_, (Option<f64>,))| score.is_some())
+    }
+
+    /// Gives the permit back, resolving once it has been removed from Redis.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a client error has occurred, in which case the permit is reclaimed
+    /// when its lease expires.
+    pub fn release(mut self) -> impl Future<Item = (), Error = Error> {
+        self.released = true;
+
+        remove(&self.client, self.set.clone(), self.id.clone())
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        self.released = true;
+
+        // The permit is removed on a spawned task rather than blocking. If no executor is
+        // running or spawning fails, the permit is reclaimed when its lease expires.
+        if tokio_executor::enter().is_err() {
+            let release = remove(&self.client, self.set.clone(), self.id.clone()).map_err(|_| ());
+            let _ = DefaultExecutor::current().spawn(Box::new(release));
+        }
+    }
+}
+
+/// The number of permits held for a key in a [`ConcurrencyLimiter`].
+///
+/// With the `serde` feature enabled, a `ConcurrencyStatus` can be serialized and deserialized.
+///
+/// [`ConcurrencyLimiter`]: struct.ConcurrencyLimiter.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConcurrencyStatus {
+    max: usize,
+    in_flight: usize,
+}
+
+impl ConcurrencyStatus {
+    /// Returns the maximum number of permits which can be held at once.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Returns the number of permits held.
+    ///
+    /// Unlike [`Status::count`], rejected requests are never included, so this value is at most
+    /// the maximum.
+    ///
+    /// [`Status::count`]: struct.Status.html#method.count
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Returns the number of permits which are available to be taken.
+    pub fn available(&self) -> usize {
+        self.max.saturating_sub(self.in_flight)
+    }
+}
+
+/// Takes a permit atomically, returning whether it was taken and the number of permits held.
+fn take(
+    con: Connection,
+    set: &str,
+    id: &str,
+    max: usize,
+    lease: Duration,
+    now: u64,
+) -> impl Future<Item = (bool, usize), Error = Error> {
+    let lease = lease.as_millis() as u64;
+
+    client::eval(TAKE_SCRIPT, 1)
+        .arg(set)
+        .arg(now)
+        .arg(max)
+        .arg(now + lease)
+        .arg(lease)
+        .arg(id)
+        .query_async(con)
+        .from_err()
+        .map(|(_, taken): (_, (bool, usize))| taken)
+}
+
+/// Removes a permit's member from its sorted set.
+fn remove(client: &RedisClient, set: String, id: String) -> impl Future<Item = (), Error = Error> {
+    client
+        .get_async_connection()
+        .from_err()
+        .and_then(move |con| {
+            redis::cmd("ZREM")
+                .arg(&set)
+                .arg(&id)
+                .query_async(con)
+                .from_err()
+                .map(|(_, ()): (_, ())| ())
+        })
+}
+
+/// Returns an identifier for a new permit, which is very unlikely to be shared by another process.
+fn permit_id() -> String {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    format!(
+        "{}:{}:{}",
+        epoch_nanos(SystemTime::now()),
+        process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst)
+    )
+}
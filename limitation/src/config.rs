@@ -0,0 +1,446 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{Builder, Error, FailurePolicy, Limiter, Priority, Tz, Window};
+use serde::de::{self, DeserializeOwned, IntoDeserializer};
+use serde::{Deserialize, Deserializer};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The backend used when a configuration has no URL
+const DEFAULT_URL: &str = "redis://127.0.0.1/";
+
+/// The settings for a [`Limiter`], loaded from a file, the environment, or any other `serde`
+/// format.
+///
+/// Every setting is optional, and a setting which isn't given keeps the [`Builder`]'s default.
+/// Durations are either a whole number of seconds or a number with a unit of `ms`, `s`, `m`,
+/// `h`, or `d`, such as `"90s"` or `"24h"`. A TOML file looks like:
+///
+/// ```toml
+/// url = "redis://127.0.0.1/"
+/// namespace = "api"
+/// limit = 1000
+/// period = "1h"
+/// window = "period"
+/// timezone = "America/Toronto"
+/// lease = 10
+/// penalties = ["1m", "10m", "1h"]
+/// forgive_after = "24h"
+/// dry_run = false
+/// timeout = "50ms"
+/// failure_policy = "open"
+/// trace_keys = false
+///
+/// [reserve]
+/// high = 0.1
+/// normal = 0.2
+/// ```
+///
+/// The URL selects the backend: a `memcached://host:port` URL uses a [`MemcachedStore`] and a
+/// `sqlite://path` URL (or `sqlite://:memory:`) a [`SqliteStore`], when their features are
+/// enabled, and any other URL is taken to be a Redis URL.
+///
+/// # Example
+///
+/// ```no_run
+/// use limitation::LimiterConfig;
+///
+/// // Settings from a file, with any set in `LIMITATION_*` environment variables taking precedence
+/// let limiter = LimiterConfig::from_file("limitation.toml")?
+///     .merge_env("LIMITATION")?
+///     .builder()?
+///     .finish()?;
+/// # Ok::<(), limitation::Error>(())
+/// ```
+///
+/// [`Builder`]: struct.Builder.html
+/// [`Limiter`]: struct.Limiter.html
+/// [`MemcachedStore`]: struct.MemcachedStore.html
+/// [`SqliteStore`]: struct.SqliteStore.html
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimiterConfig {
+    url: Option<String>,
+    limit: Option<usize>,
+    period: Option<ConfigDuration>,
+    window: Option<Window>,
+    timezone: Option<ConfigTz>,
+    namespace: Option<String>,
+    reserve: ConfigReserve,
+    lease: Option<usize>,
+    penalties: Option<Vec<ConfigDuration>>,
+    forgive_after: Option<ConfigDuration>,
+    dry_run: Option<bool>,
+    timeout: Option<ConfigDuration>,
+    failure_policy: Option<FailurePolicy>,
+    trace_keys: Option<bool>,
+}
+
+impl LimiterConfig {
+    /// Parses a configuration from a TOML document.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the document is invalid or has an unknown setting.
+    pub fn from_toml(toml: &str) -> Result<Self, Error> {
+        toml::from_str(toml).map_err(|err| Error::Config(err.to_string()))
+    }
+
+    /// Parses a configuration from a YAML document.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the document is invalid or has an unknown setting.
+    pub fn from_yaml(yaml: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(yaml).map_err(|err| Error::Config(err.to_string()))
+    }
+
+    /// Loads a configuration from a TOML or YAML file, chosen by its `.toml`, `.yaml`, or `.yml`
+    /// extension.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the file can't be read, has another extension, or is invalid.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|err| Error::Config(format!("failed to read {}: {}", path.display(), err)))?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&contents),
+            Some("yaml") | Some("yml") => Self::from_yaml(&contents),
+            _ => Err(Error::Config(format!(
+                "{} is not a .toml, .yaml, or .yml file",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Loads a configuration from environment variables named for each setting with the given
+    /// prefix.
+    ///
+    /// This is equivalent to calling [`merge_env`] on an empty configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a variable fails to parse.
+    ///
+    /// [`merge_env`]: #method.merge_env
+    pub fn from_env(prefix: &str) -> Result<Self, Error> {
+        Self::default().merge_env(prefix)
+    }
+
+    /// Overrides settings with any which are set in environment variables named for each setting
+    /// with the given prefix.
+    ///
+    /// With a prefix of `LIMITATION`, the variables are `LIMITATION_URL`, `LIMITATION_LIMIT`,
+    /// `LIMITATION_PERIOD`, and so on, in upper case. Penalties are a comma-separated list of
+    /// durations, and each reserved fraction has its own variable, such as
+    /// `LIMITATION_RESERVE_HIGH`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a variable fails to parse.
+    pub fn merge_env(mut self, prefix: &str) -> Result<Self, Error> {
+        let var = |name: &str| {
+            let name = format!("{}_{}", prefix, name);
+            env::var(&name).ok().map(|value| (name, value))
+        };
+
+        if let Some((_, value)) = var("URL") {
+            self.url = Some(value);
+        }
+        if let Some((name, value)) = var("LIMIT") {
+            self.limit = Some(parse(&name, &value)?);
+        }
+        if let Some((name, value)) = var("PERIOD") {
+            self.period = Some(parse(&name, &value)?);
+        }
+        if let Some((name, value)) = var("WINDOW") {
+            self.window = Some(parse_variant(&name, value)?);
+        }
+        if let Some((name, value)) = var("TIMEZONE") {
+            self.timezone = Some(parse(&name, &value)?);
+        }
+        if let Some((_, value)) = var("NAMESPACE") {
+            self.namespace = Some(value);
+        }
+        if let Some((name, value)) = var("RESERVE_LOW") {
+            self.reserve.low = Some(parse(&name, &value)?);
+        }
+        if let Some((name, value)) = var("RESERVE_NORMAL") {
+            self.reserve.normal = Some(parse(&name, &value)?);
+        }
+        if let Some((name, value)) = var("RESERVE_HIGH") {
+            self.reserve.high = Some(parse(&name, &value)?);
+        }
+        if let Some((name, value)) = var("LEASE") {
+            self.lease = Some(parse(&name, &value)?);
+        }
+        if let Some((name, value)) = var("PENALTIES") {
+            self.penalties = Some(
+                value
+                    .split(',')
+                    .map(|ban| parse(&name, ban.trim()))
+                    .collect::<Result<_, _>>()?,
+            );
+        }
+        if let Some((name, value)) = var("FORGIVE_AFTER") {
+            self.forgive_after = Some(parse(&name, &value)?);
+        }
+        if let Some((name, value)) = var("DRY_RUN") {
+            self.dry_run = Some(parse(&name, &value)?);
+        }
+        if let Some((name, value)) = var("TIMEOUT") {
+            self.timeout = Some(parse(&name, &value)?);
+        }
+        if let Some((name, value)) = var("FAILURE_POLICY") {
+            self.failure_policy = Some(parse_variant(&name, value)?);
+        }
+        if let Some((name, value)) = var("TRACE_KEYS") {
+            self.trace_keys = Some(parse(&name, &value)?);
+        }
+
+        Ok(self)
+    }
+
+    /// Returns the backend URL, if one is set.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Returns a [`Builder`] for the configured backend with every configured setting applied.
+    ///
+    /// Without a URL, the backend is a Redis server at `redis://127.0.0.1/`. Settings can still
+    /// be changed on the returned `Builder`, such as to add an observer, before finishing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the URL needs a feature which isn't enabled, or the store it selects
+    /// fails to open.
+    ///
+    /// [`Builder`]: struct.Builder.html
+    pub fn builder(&self) -> Result<Builder<'_>, Error> {
+        let url = self.url.as_deref().unwrap_or(DEFAULT_URL);
+
+        let mut builder = if let Some(addr) = url.strip_prefix("memcached://") {
+            memcached_builder(addr)?
+        } else if let Some(path) = url.strip_prefix("sqlite://") {
+            sqlite_builder(path)?
+        } else {
+            Limiter::build(url)
+        };
+        builder.config(self);
+
+        Ok(builder)
+    }
+
+    /// Applies every configured setting to a `Builder`.
+    pub(crate) fn apply(&self, builder: &mut Builder<'_>) {
+        if let Some(limit) = self.limit {
+            builder.limit(limit);
+        }
+        if let Some(ref period) = self.period {
+            builder.period(period.0);
+        }
+        if let Some(window) = self.window {
+            builder.window(window);
+        }
+        if let Some(ref timezone) = self.timezone {
+            builder.timezone(timezone.0);
+        }
+        if let Some(ref namespace) = self.namespace {
+            builder.namespace(namespace.as_str());
+        }
+        for (priority, fraction) in self.reserve.fractions() {
+            builder.reserve(priority, fraction);
+        }
+        if let Some(lease) = self.lease {
+            builder.lease(lease);
+        }
+        if let Some(ref penalties) = self.penalties {
+            builder.penalties(penalties.iter().map(|ban| ban.0));
+        }
+        if let Some(ref forgive_after) = self.forgive_after {
+            builder.forgive_after(forgive_after.0);
+        }
+        if let Some(dry_run) = self.dry_run {
+            builder.dry_run(dry_run);
+        }
+        if let Some(ref timeout) = self.timeout {
+            builder.timeout(timeout.0);
+        }
+        if let Some(failure_policy) = self.failure_policy {
+            builder.failure_policy(failure_policy);
+        }
+        if let Some(trace_keys) = self.trace_keys {
+            builder.trace_keys(trace_keys);
+        }
+    }
+}
+
+/// Returns a `Builder` for a `MemcachedStore` at the given address.
+#[cfg(feature = "memcached")]
+fn memcached_builder(addr: &str) -> Result<Builder<'static>, Error> {
+    Ok(Limiter::build_with_store(crate::MemcachedStore::open(
+        addr,
+    )?))
+}
+
+#[cfg(not(feature = "memcached"))]
+fn memcached_builder(_addr: &str) -> Result<Builder<'static>, Error> {
+    Err(Error::Config(
+        "memcached URLs require the `memcached` feature".to_string(),
+    ))
+}
+
+/// Returns a `Builder` for a `SqliteStore` with a database at the given path.
+#[cfg(feature = "sqlite")]
+fn sqlite_builder(path: &str) -> Result<Builder<'static>, Error> {
+    let store = if path == ":memory:" {
+        crate::SqliteStore::open_in_memory()?
+    } else {
+        crate::SqliteStore::open(path)?
+    };
+
+    Ok(Limiter::build_with_store(store))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_builder(_path: &str) -> Result<Builder<'static>, Error> {
+    Err(Error::Config(
+        "sqlite URLs require the `sqlite` feature".to_string(),
+    ))
+}
+
+/// The fractions of the limit reserved for each priority class.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigReserve {
+    low: Option<f64>,
+    normal: Option<f64>,
+    high: Option<f64>,
+}
+
+impl ConfigReserve {
+    /// Returns the fraction reserved for each class which has one.
+    fn fractions(self) -> impl Iterator<Item = (Priority, f64)> {
+        vec![
+            (Priority::Low, self.low),
+            (Priority::Normal, self.normal),
+            (Priority::High, self.high),
+        ]
+        .into_iter()
+        .filter_map(|(priority, fraction)| fraction.map(|fraction| (priority, fraction)))
+    }
+}
+
+/// A `Duration` given as a whole number of seconds or a number with a unit.
+#[derive(Clone, Copy, Debug)]
+struct ConfigDuration(Duration);
+
+impl FromStr for ConfigDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (amount, unit) = s.split_at(split);
+        let amount: u64 = amount
+            .parse()
+            .map_err(|_| format!("invalid duration: {:?}", s))?;
+
+        let secs = |per_unit: u64| {
+            amount
+                .checked_mul(per_unit)
+                .map(Duration::from_secs)
+                .ok_or_else(|| format!("duration is too long: {:?}", s))
+        };
+
+        let duration = match unit.trim() {
+            "ms" => Duration::from_millis(amount),
+            "" | "s" => Duration::from_secs(amount),
+            "m" => secs(60)?,
+            "h" => secs(60 * 60)?,
+            "d" => secs(60 * 60 * 24)?,
+            _ => return Err(format!("invalid duration unit: {:?}", s)),
+        };
+
+        Ok(ConfigDuration(duration))
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfigDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = ConfigDuration;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number of seconds or a duration such as \"90s\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, secs: u64) -> Result<Self::Value, E> {
+                Ok(ConfigDuration(Duration::from_secs(secs)))
+            }
+
+            fn visit_i64<E: de::Error>(self, secs: i64) -> Result<Self::Value, E> {
+                if secs < 0 {
+                    return Err(E::invalid_value(de::Unexpected::Signed(secs), &self));
+                }
+                self.visit_u64(secs as u64)
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                s.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// A `Tz` given by its IANA name, such as `"America/Toronto"`.
+#[derive(Clone, Copy, Debug)]
+struct ConfigTz(Tz);
+
+impl FromStr for ConfigTz {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(ConfigTz)
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfigTz {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Parses the value of an environment variable.
+fn parse<T>(name: &str, value: &str) -> Result<T, Error>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|err| Error::Config(format!("invalid {} ({})", name, err)))
+}
+
+/// Parses the value of an environment variable naming a variant of an enum, in any case.
+fn parse_variant<T: DeserializeOwned>(name: &str, value: String) -> Result<T, Error> {
+    let value: de::value::StringDeserializer<de::value::Error> =
+        value.to_lowercase().into_deserializer();
+
+    T::deserialize(value).map_err(|err| Error::Config(format!("invalid {} ({})", name, err)))
+}
@@ -0,0 +1,414 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::client::RedisClient;
+use crate::{trace, Decision, Error, Observer, Outcome, Status};
+use redis::{Connection, PubSub, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+
+/// The number of events held while waiting to be published, beyond which events are dropped
+const QUEUE_CAPACITY: usize = 1024;
+/// The approximate number of events kept in a stream
+const STREAM_MAX_LEN: usize = 10_000;
+/// The number of events read from a stream at a time
+const STREAM_READ_COUNT: usize = 100;
+/// The field of a stream entry holding the event
+const STREAM_FIELD: &str = "event";
+
+/// Where limit events are published in Redis.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Destination {
+    /// A Pub/Sub channel.
+    ///
+    /// Events are only delivered to subscribers which are connected when they're published.
+    Channel(String),
+    /// A Stream.
+    ///
+    /// Each event is added as an entry with a single `event` field holding the payload. Roughly
+    /// the 10,000 most recent events are kept, so subscribers can catch up on events published
+    /// while they weren't connected.
+    Stream(String),
+}
+
+/// The kind of an [`Event`].
+///
+/// [`Event`]: struct.Event.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// A request was rejected because its key is banned for repeatedly exceeding the limit.
+    Banned,
+    /// A request was rejected because its key exceeded the limit.
+    LimitExceeded,
+    /// A request would have been rejected, because its key exceeded the limit or is banned, but
+    /// was allowed in dry-run mode.
+    WouldDeny,
+}
+
+/// An event published by an [`EventPublisher`] when a request is rejected.
+///
+/// The payload is JSON, holding the kind of event, the key verbatim, and the key's [`Status`]:
+///
+/// ```json
+/// {
+///   "kind": "limit_exceeded",
+///   "key": "alice",
+///   "status": {
+///     "limit": 5,
+///     "remaining": 0,
+///     "count": 6,
+///     "window_start_epoch_utc": 1571600000,
+///     "reset_epoch_utc": 1571600060,
+///     "retry_after_ms": 42000
+///   }
+/// }
+/// ```
+///
+/// The `kind` is `banned` when the key is banned, in which case the status also has a
+/// `ban_expires_epoch_utc` field. In dry-run mode the `kind` is `would_deny` and the status has a
+/// `would_deny` field, as the request was allowed.
+///
+/// [`EventPublisher`]: struct.EventPublisher.html
+/// [`Status`]: struct.Status.html
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    kind: EventKind,
+    key: String,
+    status: Status,
+}
+
+impl Event {
+    /// Returns the kind of event.
+    pub fn kind(&self) -> EventKind {
+        self.kind
+    }
+
+    /// Returns the key whose request was rejected.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns the `Status` of the key.
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// Creates an `Event` for a decision which rejected a request, or would have in dry-run mode.
+    ///
+    /// Keys on the denylist are rejected on purpose, so no event is created for them.
+    fn from_decision(decision: &Decision<'_>) -> Option<Self> {
+        let dry_run = match decision.outcome() {
+            Outcome::Denied => false,
+            Outcome::WouldDeny => true,
+            Outcome::Allowed | Outcome::Error => return None,
+        };
+        let status = decision
+            .status()
+            .filter(|status| status.listing().is_none())?;
+        let kind = if dry_run {
+            EventKind::WouldDeny
+        } else if status.banned() {
+            EventKind::Banned
+        } else {
+            EventKind::LimitExceeded
+        };
+
+        Some(Event {
+            kind,
+            key: decision.key().to_string(),
+            status: status.clone(),
+        })
+    }
+
+    /// Decodes an event from its payload.
+    fn decode(payload: &str) -> Result<Self, Error> {
+        serde_json::from_str(payload).map_err(|err| Error::Event(Box::new(err)))
+    }
+}
+
+/// An `Observer` which publishes an [`Event`] to Redis each time a request is rejected.
+///
+/// Events are published from a background thread so that counting is never held up by Redis. If
+/// events are rejected faster than they can be published, or Redis can't be reached, events are
+/// dropped rather than queued without bound. Publishing stops when the `EventPublisher` and every
+/// `Limiter` it's registered with are dropped.
+///
+/// This type is only available with the `events` feature enabled.
+///
+/// # Example
+///
+/// ```no_run
+/// use limitation::{Destination, EventPublisher, Limiter};
+///
+/// let limiter = Limiter::build("redis://127.0.0.1/")
+///     .observer(EventPublisher::new(
+///         "redis://127.0.0.1/",
+///         Destination::Channel("limitation:events".to_string()),
+///     )?)
+///     .finish()?;
+/// # Ok::<(), limitation::Error>(())
+/// ```
+///
+/// [`Event`]: struct.Event.html
+#[derive(Debug)]
+pub struct EventPublisher {
+    /// The queue of events to be published
+    events: SyncSender<Event>,
+}
+
+impl EventPublisher {
+    /// Creates a new `EventPublisher` which publishes events to a destination in Redis.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the Redis URL fails to parse, or if the background thread can't be
+    /// started.
+    pub fn new(redis_url: &str, destination: Destination) -> Result<Self, Error> {
+        let client = RedisClient::open(redis_url)?;
+        let (events, queued) = mpsc::sync_channel(QUEUE_CAPACITY);
+
+        thread::Builder::new()
+            .name("limitation-events".to_string())
+            .spawn(move || publish_all(&client, &destination, queued))
+            .map_err(|err| Error::Config(format!("event publisher failed to start ({})", err)))?;
+
+        Ok(EventPublisher { events })
+    }
+}
+
+impl Observer for EventPublisher {
+    fn on_decision(&self, decision: &Decision<'_>) {
+        if let Some(event) = Event::from_decision(decision) {
+            if let Err(TrySendError::Full(_)) = self.events.try_send(event) {
+                trace::event_dropped();
+            }
+        }
+    }
+}
+
+/// Publishes each queued event until the queue is closed, reconnecting after a failure.
+fn publish_all(client: &RedisClient, destination: &Destination, queued: Receiver<Event>) {
+    let mut con = None;
+
+    for event in queued {
+        let result = match con {
+            Some(ref mut con) => publish(con, destination, &event),
+            None => client
+                .get_connection()
+                .map_err(Error::from)
+                .and_then(|mut connected| {
+                    let published = publish(&mut connected, destination, &event);
+                    con = Some(connected);
+                    published
+                }),
+        };
+
+        if let Err(err) = result {
+            trace::event_failed(&err);
+            con = None;
+        }
+    }
+}
+
+/// Publishes an event to a destination.
+fn publish(con: &mut Connection, destination: &Destination, event: &Event) -> Result<(), Error> {
+    let payload = serde_json::to_string(event).expect("event should serialize");
+
+    match destination {
+        Destination::Channel(channel) => redis::cmd("PUBLISH")
+            .arg(channel)
+            .arg(payload)
+            .query::<usize>(con)
+            .map(|_| ()),
+        Destination::Stream(stream) => redis::cmd("XADD")
+            .arg(stream)
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(STREAM_MAX_LEN)
+            .arg("*")
+            .arg(STREAM_FIELD)
+            .arg(payload)
+            .query::<String>(con)
+            .map(|_| ()),
+    }
+    .map_err(Error::from)
+}
+
+/// A consumer of the events published by an [`EventPublisher`].
+///
+/// Events are read with [`events`], which blocks until each event arrives. A subscriber to a
+/// stream starts with the oldest event still kept in the stream.
+///
+/// This type is only available with the `events` feature enabled.
+///
+/// # Example
+///
+/// ```no_run
+/// use limitation::{Destination, EventSubscriber};
+///
+/// let mut subscriber = EventSubscriber::open(
+///     "redis://127.0.0.1/",
+///     Destination::Stream("limitation:events".to_string()),
+/// )?;
+///
+/// for event in subscriber.events()? {
+///     let event = event?;
+///     println!("{:?}: {}", event.kind(), event.key());
+/// }
+/// # Ok::<(), limitation::Error>(())
+/// ```
+///
+/// [`EventPublisher`]: struct.EventPublisher.html
+/// [`events`]: #method.events
+pub struct EventSubscriber {
+    /// The connection events are read from
+    con: Connection,
+    /// Where events are read from
+    destination: Destination,
+    /// The id of the last stream entry read
+    last_id: String,
+}
+
+impl EventSubscriber {
+    /// Connects to Redis and returns a new `EventSubscriber` for a destination.
+    ///
+    /// Note that this method connects to the Redis server, which is a **synchronous** operation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the Redis URL fails to parse or the client fails to connect.
+    pub fn open(redis_url: &str, destination: Destination) -> Result<Self, Error> {
+        Ok(EventSubscriber {
+            con: RedisClient::open(redis_url)?.get_connection()?,
+            destination,
+            last_id: "0".to_string(),
+        })
+    }
+
+    /// Returns an iterator over events as they arrive.
+    ///
+    /// Each call to `next` blocks until an event arrives. A subscriber to a channel is subscribed
+    /// until the iterator is dropped, and a subscriber to a stream carries on from the last event
+    /// it read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if subscribing to a channel fails. Each item is an `Err` if a client error
+    /// has occurred or the payload isn't a valid event.
+    pub fn events(&mut self) -> Result<Events<'_>, Error> {
+        let source = match self.destination {
+            Destination::Channel(ref channel) => {
+                let mut pubsub = self.con.as_pubsub();
+                pubsub.subscribe(channel)?;
+                Source::Channel(pubsub)
+            }
+            Destination::Stream(ref stream) => Source::Stream {
+                con: &mut self.con,
+                stream,
+                last_id: &mut self.last_id,
+                read: VecDeque::new(),
+            },
+        };
+
+        Ok(Events { source })
+    }
+}
+
+/// An iterator over the events read by an [`EventSubscriber`].
+///
+/// This is created by the [`events`] method.
+///
+/// [`EventSubscriber`]: struct.EventSubscriber.html
+/// [`events`]: struct.EventSubscriber.html#method.events
+pub struct Events<'a> {
+    source: Source<'a>,
+}
+
+/// Where an `Events` iterator reads from.
+enum Source<'a> {
+    /// A subscription to a channel
+    Channel(PubSub<'a>),
+    /// A stream, with the entries read but not yet returned
+    Stream {
+        con: &'a mut Connection,
+        stream: &'a str,
+        last_id: &'a mut String,
+        read: VecDeque<String>,
+    },
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let payload = match self.source {
+            Source::Channel(ref mut pubsub) => pubsub
+                .get_message()
+                .and_then(|message| message.get_payload::<String>())
+                .map_err(Error::from),
+            Source::Stream {
+                ref mut con,
+                stream,
+                ref mut last_id,
+                ref mut read,
+            } => read_stream(con, stream, last_id, read),
+        };
+
+        Some(payload.and_then(|payload| Event::decode(&payload)))
+    }
+}
+
+/// Returns the payload of the next entry in a stream, waiting for entries to be added once every
+/// entry has been read.
+fn read_stream(
+    con: &mut Connection,
+    stream: &str,
+    last_id: &mut String,
+    read: &mut VecDeque<String>,
+) -> Result<String, Error> {
+    while read.is_empty() {
+        // A stream and an entry are each a pair of a name and a list, which redis-rs would read
+        // as a flat list of pairs, so the reply is read a level at a time
+        let reply: Option<Vec<Vec<Value>>> = redis::cmd("XREAD")
+            .arg("COUNT")
+            .arg(STREAM_READ_COUNT)
+            .arg("BLOCK")
+            .arg(0)
+            .arg("STREAMS")
+            .arg(stream)
+            .arg(last_id.as_str())
+            .query(con)?;
+
+        for entries in reply
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|pair| pair.get(1))
+        {
+            for entry in redis::from_redis_value::<Vec<Vec<Value>>>(entries)? {
+                let (id, fields) = match entry.as_slice() {
+                    [id, fields] => (
+                        redis::from_redis_value::<String>(id)?,
+                        redis::from_redis_value::<Vec<String>>(fields)?,
+                    ),
+                    _ => continue,
+                };
+                let payload = fields
+                    .chunks(2)
+                    .find(|pair| pair[0] == STREAM_FIELD)
+                    .and_then(|pair| pair.get(1));
+                if let Some(payload) = payload {
+                    read.push_back(payload.clone());
+                }
+                *last_id = id;
+            }
+        }
+    }
+
+    Ok(read
+        .pop_front()
+        .expect("stream entries should have been read"))
+}
@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::Error;
+
+/// What a [`Limiter`] does with a request when its backend fails or times out.
+///
+/// The policy is set with [`Builder::failure_policy`] and applies to [`Limiter::count`] and the
+/// methods built on it, [`Limiter::count_path`], and [`ByteLimiter::check`]. A backend failure is
+/// a client, authentication, or store error, or a decision which took longer than the timeout set
+/// with [`Builder::timeout`].
+///
+/// # Example
+///
+/// ```no_run
+/// use limitation::{FailurePolicy, Limiter};
+/// use std::time::Duration;
+///
+/// // Let requests through if Redis doesn't answer within 50ms
+/// let limiter = Limiter::build("redis://127.0.0.1/")
+///     .timeout(Duration::from_millis(50))
+///     .failure_policy(FailurePolicy::Open)
+///     .finish()?;
+/// # Ok::<(), limitation::Error>(())
+/// ```
+///
+/// [`Builder::failure_policy`]: struct.Builder.html#method.failure_policy
+/// [`Builder::timeout`]: struct.Builder.html#method.timeout
+/// [`ByteLimiter::check`]: struct.ByteLimiter.html#method.check
+/// [`Limiter`]: struct.Limiter.html
+/// [`Limiter::count`]: struct.Limiter.html#method.count
+/// [`Limiter::count_path`]: struct.Limiter.html#method.count_path
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum FailurePolicy {
+    /// The request is allowed without being counted.
+    ///
+    /// The returned [`Status`] reports a count of `0` in the current window. Observers are still
+    /// notified of the failure.
+    ///
+    /// [`Status`]: struct.Status.html
+    Open,
+    /// The failure is returned as an `Err`, so the request isn't allowed.
+    ///
+    /// This is the default.
+    #[default]
+    Closed,
+}
+
+impl FailurePolicy {
+    /// Returns whether an error is a failure of the backend, to which the policy applies.
+    pub(crate) fn applies_to(err: &Error) -> bool {
+        matches!(
+            err,
+            Error::Auth(_) | Error::Client(_) | Error::Store(_) | Error::Timeout(_)
+        )
+    }
+}
@@ -0,0 +1,48 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/// A path of keys from the broadest to the most specific, each with its own limit, which are
+/// counted together by [`Limiter::count_path`].
+///
+/// A path such as organization, then user, then token lets a request count against a token's own
+/// limit as well as the limits pooled by its user and organization. A request is rejected if any
+/// level of the path is exhausted.
+///
+/// # Example
+///
+/// ```
+/// use limitation::KeyPath;
+///
+/// let mut path = KeyPath::new();
+/// path.level("org:acme", 10_000)
+///     .level("user:alice", 1_000)
+///     .level("token:abc123", 100);
+/// ```
+///
+/// [`Limiter::count_path`]: struct.Limiter.html#method.count_path
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyPath {
+    /// Each level's key and limit, from the broadest to the most specific
+    levels: Vec<(String, usize)>,
+}
+
+impl KeyPath {
+    /// Creates a new, empty `KeyPath`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a level below the levels already in the path, with its own per-period limit.
+    pub fn level<K: Into<String>>(&mut self, key: K, limit: usize) -> &mut Self {
+        self.levels.push((key.into(), limit));
+        self
+    }
+
+    /// Returns each level's key and limit, from the broadest to the most specific.
+    pub fn levels(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.levels
+            .iter()
+            .map(|(key, limit)| (key.as_str(), *limit))
+    }
+}
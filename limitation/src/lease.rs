@@ -0,0 +1,121 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::duration_between;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Batches of quota leased from a store, which are served locally until used up or expired.
+#[derive(Debug)]
+pub(crate) struct Leases {
+    /// How many requests are tracked in the store for each lease
+    batch: usize,
+    /// The current lease for each key
+    held: Mutex<HashMap<String, Lease>>,
+}
+
+/// Unused requests leased for one key's window.
+#[derive(Debug)]
+struct Lease {
+    /// The key in the store which the lease was tracked on
+    store_key: String,
+    /// The number of leased requests which haven't been used
+    remaining: usize,
+    /// The count to report for the next request served from the lease
+    next_count: usize,
+    /// When the window the lease was tracked in ends
+    expires_at: SystemTime,
+}
+
+impl Leases {
+    /// Creates a new, empty set of leases tracked in batches of the given size.
+    pub(crate) fn new(batch: usize) -> Self {
+        Leases {
+            batch: batch.max(1),
+            held: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns how many requests are tracked in the store for each lease.
+    pub(crate) fn batch(&self) -> usize {
+        self.batch
+    }
+
+    /// Serves one request on a key from its lease, returning the count and time remaining in the
+    /// window, or `None` if a new lease is needed.
+    ///
+    /// A request whose count would be over its ceiling isn't served, but the lease is kept for
+    /// requests with a higher ceiling.
+    pub(crate) fn take(
+        &self,
+        key: &str,
+        ceiling: usize,
+        now: SystemTime,
+    ) -> Option<(usize, Duration)> {
+        let mut held = self.held.lock().expect("lease lock was poisoned");
+
+        let lease = held.get_mut(key)?;
+        if lease.remaining == 0 || lease.expires_at <= now {
+            held.remove(key);
+            return None;
+        }
+        if lease.next_count > ceiling {
+            return None;
+        }
+        lease.remaining -= 1;
+        lease.next_count += 1;
+
+        Some((
+            lease.next_count - 1,
+            duration_between(now, lease.expires_at),
+        ))
+    }
+
+    /// Records a newly tracked lease for a key, whose first unused request will report
+    /// `next_count`.
+    ///
+    /// If the key still holds a lease in the same window, which happens when concurrent requests
+    /// each track a batch, the unused requests are added together.
+    pub(crate) fn grant(
+        &self,
+        key: String,
+        store_key: String,
+        remaining: usize,
+        next_count: usize,
+        expires_at: SystemTime,
+        now: SystemTime,
+    ) {
+        let mut held = self.held.lock().expect("lease lock was poisoned");
+
+        match held.get_mut(&key) {
+            Some(ref mut lease) if lease.store_key == store_key && lease.expires_at > now => {
+                lease.remaining += remaining;
+            }
+            _ => {
+                held.insert(
+                    key,
+                    Lease {
+                        store_key,
+                        remaining,
+                        next_count,
+                        expires_at,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Removes every lease, returning the store key and unused requests of each lease whose
+    /// window hasn't ended.
+    pub(crate) fn drain(&self, now: SystemTime) -> Vec<(String, usize)> {
+        let mut held = self.held.lock().expect("lease lock was poisoned");
+
+        held.drain()
+            .map(|(_, lease)| lease)
+            .filter(|lease| lease.remaining > 0 && lease.expires_at > now)
+            .map(|lease| (lease.store_key, lease.remaining))
+            .collect()
+    }
+}
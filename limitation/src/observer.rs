@@ -0,0 +1,148 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{Error, Status};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+mod metrics;
+
+#[cfg(feature = "metrics")]
+pub use self::metrics::MetricsObserver;
+
+/// A hook which is notified of every decision made by a [`Limiter`].
+///
+/// Observers are registered with the [`observer`] method on the builder and are called
+/// synchronously once each [`count`] completes, so implementations should be cheap and must not
+/// block.
+///
+/// # Example
+///
+/// ```
+/// use limitation::{Decision, Observer};
+///
+/// #[derive(Debug)]
+/// struct Printer;
+///
+/// impl Observer for Printer {
+///     fn on_decision(&self, decision: &Decision) {
+///         println!("{} -> {:?} in {:?}", decision.key(), decision.outcome(), decision.latency());
+///     }
+/// }
+/// ```
+///
+/// [`count`]: struct.Limiter.html#method.count
+/// [`Limiter`]: struct.Limiter.html
+/// [`observer`]: struct.Builder.html#method.observer
+pub trait Observer: fmt::Debug + Send + Sync {
+    /// Called when a decision has been made for a key.
+    fn on_decision(&self, decision: &Decision<'_>);
+}
+
+/// The result of a [`Limiter`] counting a request on a key.
+///
+/// [`Limiter`]: struct.Limiter.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// The request was under the limit.
+    Allowed,
+    /// The request exceeded the limit.
+    Denied,
+    /// The request exceeded the limit but was allowed because the Limiter is in dry-run mode.
+    WouldDeny,
+    /// The backend failed, so no decision could be made.
+    Error,
+}
+
+impl Outcome {
+    /// Returns a short, lowercase name for the outcome, suitable for a metric label.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Allowed => "allowed",
+            Outcome::Denied => "denied",
+            Outcome::WouldDeny => "would_deny",
+            Outcome::Error => "error",
+        }
+    }
+}
+
+/// A report of a single decision, passed to each [`Observer`].
+///
+/// [`Observer`]: trait.Observer.html
+#[derive(Debug)]
+pub struct Decision<'a> {
+    key: &'a str,
+    outcome: Outcome,
+    latency: Duration,
+    result: &'a Result<Status, Error>,
+}
+
+impl<'a> Decision<'a> {
+    /// Creates a new `Decision` from the result of counting a key.
+    pub(crate) fn new(key: &'a str, latency: Duration, result: &'a Result<Status, Error>) -> Self {
+        let outcome = match result {
+            Ok(status) if status.would_deny() => Outcome::WouldDeny,
+            Ok(_) => Outcome::Allowed,
+            Err(Error::LimitExceeded(_)) => Outcome::Denied,
+            Err(_) => Outcome::Error,
+        };
+
+        Decision {
+            key,
+            outcome,
+            latency,
+            result,
+        }
+    }
+
+    /// Returns the key which was counted.
+    pub fn key(&self) -> &str {
+        self.key
+    }
+
+    /// Returns the outcome of the decision.
+    pub fn outcome(&self) -> Outcome {
+        self.outcome
+    }
+
+    /// Returns how long the decision took, including the round trip to the backend.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Returns the `Status` of the key, unless the backend failed.
+    pub fn status(&self) -> Option<&Status> {
+        match self.result {
+            Ok(status) | Err(Error::LimitExceeded(status)) => Some(status),
+            Err(_) => None,
+        }
+    }
+
+    /// Returns the backend error, if the backend failed.
+    pub fn error(&self) -> Option<&Error> {
+        match self.result {
+            Err(Error::LimitExceeded(_)) | Ok(_) => None,
+            Err(err) => Some(err),
+        }
+    }
+}
+
+/// Notifies each observer of a decision.
+pub(crate) fn notify(
+    observers: &[Arc<dyn Observer>],
+    key: &str,
+    latency: Duration,
+    result: &Result<Status, Error>,
+) {
+    if observers.is_empty() {
+        return;
+    }
+
+    let decision = Decision::new(key, latency, result);
+    for observer in observers {
+        observer.on_decision(&decision);
+    }
+}
@@ -0,0 +1,64 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{Decision, Observer, Outcome};
+
+/// The default prefix for metric names
+const DEFAULT_PREFIX: &str = "limitation";
+
+/// An `Observer` which reports decisions to the [`metrics`] crate facade.
+///
+/// The following metrics are emitted, where `<prefix>` defaults to `limitation`:
+///
+/// - `<prefix>_decisions_total`: a counter labeled with an `outcome` of `allowed`, `denied`,
+///   `error`, or `would_deny` for requests allowed in dry-run mode
+/// - `<prefix>_backend_errors_total`: a counter of failed backend calls
+/// - `<prefix>_decision_duration_seconds`: a histogram of decision latency, including the round
+///   trip to the backend
+///
+/// Keys are intentionally not used as labels as they are typically unbounded in number.
+///
+/// This type is only available with the `metrics` feature enabled.
+///
+/// [`metrics`]: https://docs.rs/metrics
+#[derive(Clone, Debug)]
+pub struct MetricsObserver {
+    decisions: String,
+    errors: String,
+    duration: String,
+}
+
+impl MetricsObserver {
+    /// Creates a new `MetricsObserver` using the default metric name prefix.
+    pub fn new() -> Self {
+        Self::with_prefix(DEFAULT_PREFIX)
+    }
+
+    /// Creates a new `MetricsObserver` using the given metric name prefix.
+    pub fn with_prefix(prefix: &str) -> Self {
+        MetricsObserver {
+            decisions: format!("{}_decisions_total", prefix),
+            errors: format!("{}_backend_errors_total", prefix),
+            duration: format!("{}_decision_duration_seconds", prefix),
+        }
+    }
+}
+
+impl Default for MetricsObserver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer for MetricsObserver {
+    fn on_decision(&self, decision: &Decision<'_>) {
+        let outcome = decision.outcome();
+
+        ::metrics::counter!(self.decisions.clone(), "outcome" => outcome.as_str()).increment(1);
+        if outcome == Outcome::Error {
+            ::metrics::counter!(self.errors.clone()).increment(1);
+        }
+        ::metrics::histogram!(self.duration.clone()).record(decision.latency().as_secs_f64());
+    }
+}
@@ -0,0 +1,113 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{Store, StoreFuture};
+use futures::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Escalating bans for keys which keep exceeding their limit.
+#[derive(Debug)]
+pub(crate) struct Penalties {
+    /// The length of the ban for each successive violation, the last of which repeats
+    bans: Vec<Duration>,
+    /// How long violations are remembered after the first one
+    forgive_after: Duration,
+    /// The Limiter's namespace, or an empty string if it has none
+    namespace: String,
+}
+
+impl Penalties {
+    /// Creates a new set of penalties, or `None` if there are no bans to apply.
+    pub(crate) fn new(
+        bans: Vec<Duration>,
+        forgive_after: Duration,
+        namespace: Option<String>,
+    ) -> Option<Self> {
+        if bans.is_empty() {
+            None
+        } else {
+            Some(Penalties {
+                bans,
+                forgive_after,
+                namespace: namespace.unwrap_or_default(),
+            })
+        }
+    }
+
+    /// Returns the time remaining on a key's ban, or `None` if the key isn't banned.
+    pub(crate) fn banned(
+        &self,
+        store: &dyn Store,
+        key: &str,
+        now: SystemTime,
+    ) -> StoreFuture<Option<Duration>> {
+        Box::new(store.peek(self.ban_key(key), now).map(|ban| {
+            ban.map(|(_, ttl)| ttl)
+                .filter(|ttl| *ttl > Duration::from_secs(0))
+        }))
+    }
+
+    /// Records a violation for a key and bans it, returning the length of the ban.
+    ///
+    /// Each violation within the forgiveness period leads to a longer ban than the last, until the
+    /// longest ban is reached.
+    pub(crate) fn punish(
+        &self,
+        store: Arc<dyn Store>,
+        key: &str,
+        now: SystemTime,
+    ) -> StoreFuture<Duration> {
+        let bans = self.bans.clone();
+        let ban_key = self.ban_key(key);
+
+        Box::new(
+            store
+                .track(self.violations_key(key), self.forgive_after, now)
+                .and_then(move |(violations, _)| {
+                    let ban = bans[violations.max(1).min(bans.len()) - 1];
+                    store.track(ban_key, ban, now).map(|(_, ttl)| ttl)
+                }),
+        )
+    }
+
+    /// Returns the length of the ban a key would be given for a violation now, without recording
+    /// the violation or the ban.
+    ///
+    /// This is used in dry-run mode, where a violation mustn't lead to a real ban.
+    pub(crate) fn would_punish(
+        &self,
+        store: &dyn Store,
+        key: &str,
+        now: SystemTime,
+    ) -> StoreFuture<Duration> {
+        let bans = self.bans.clone();
+
+        Box::new(
+            store
+                .peek(self.violations_key(key), now)
+                .map(move |violations| {
+                    let violations = violations
+                        .filter(|(_, ttl)| *ttl > Duration::from_secs(0))
+                        .map_or(0, |(violations, _)| violations);
+                    bans[(violations + 1).min(bans.len()) - 1]
+                }),
+        )
+    }
+
+    /// Returns the store key which marks a key as banned.
+    fn ban_key(&self, key: &str) -> String {
+        format!("{}{}penalty:ban:{}", self.namespace, crate::RESERVED, key)
+    }
+
+    /// Returns the store key which counts a key's violations.
+    fn violations_key(&self, key: &str) -> String {
+        format!(
+            "{}{}penalty:violations:{}",
+            self.namespace,
+            crate::RESERVED,
+            key
+        )
+    }
+}
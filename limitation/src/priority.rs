@@ -0,0 +1,103 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::Error;
+
+/// The priority class of a request counted with [`Limiter::count_with_priority`].
+///
+/// A fraction of the limit can be reserved for a class and every class above it with
+/// [`Builder::reserve`]. Requests of a lower class are then rejected once only the reserved
+/// capacity is left, so that interactive traffic still gets through when background jobs have
+/// nearly exhausted a shared quota. Requests counted with [`Limiter::count`] are of `Normal`
+/// priority.
+///
+/// # Example
+///
+/// ```no_run
+/// use futures::Future;
+/// use limitation::{Limiter, Priority};
+///
+/// // Background jobs may only use the first 70% of the limit
+/// let limiter = Limiter::build("redis://127.0.0.1/")
+///     .limit(1000)
+///     .reserve(Priority::High, 0.1)
+///     .reserve(Priority::Normal, 0.2)
+///     .finish()?;
+///
+/// let status = limiter.count_with_priority("alice", Priority::Low).wait()?;
+/// # Ok::<(), limitation::Error>(())
+/// ```
+///
+/// [`Builder::reserve`]: struct.Builder.html#method.reserve
+/// [`Limiter::count`]: struct.Limiter.html#method.count
+/// [`Limiter::count_with_priority`]: struct.Limiter.html#method.count_with_priority
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Requests which can wait, such as background jobs.
+    Low,
+    /// Ordinary requests.
+    ///
+    /// This is the default.
+    #[default]
+    Normal,
+    /// Requests which should get through whenever possible, such as interactive traffic.
+    High,
+}
+
+impl Priority {
+    /// Returns the index of the class, from lowest to highest.
+    fn index(self) -> usize {
+        match self {
+            Priority::Low => 0,
+            Priority::Normal => 1,
+            Priority::High => 2,
+        }
+    }
+}
+
+/// The fractions of a limit reserved for each priority class.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Reserves {
+    /// The fraction reserved for each class, indexed from lowest to highest
+    fractions: [f64; 3],
+}
+
+impl Reserves {
+    /// Reserves a fraction of the limit for a class.
+    pub(crate) fn set(&mut self, priority: Priority, fraction: f64) {
+        self.fractions[priority.index()] = fraction;
+    }
+
+    /// Checks that every fraction is between `0.0` and `1.0` and that they add up to no more
+    /// than `1.0`.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if let Some(fraction) = self
+            .fractions
+            .iter()
+            .find(|fraction| !(**fraction >= 0.0 && **fraction <= 1.0))
+        {
+            return Err(Error::Config(format!(
+                "reserved fraction {} must be between 0.0 and 1.0",
+                fraction
+            )));
+        }
+        let total: f64 = self.fractions.iter().sum();
+        if total > 1.0 {
+            return Err(Error::Config(format!(
+                "reserved fractions add up to {}, which is more than 1.0",
+                total
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the most requests a class may use of a limit, leaving the capacity reserved for
+    /// every class above it.
+    pub(crate) fn ceiling(&self, limit: usize, priority: Priority) -> usize {
+        let reserved: f64 = self.fractions[priority.index() + 1..].iter().sum();
+
+        limit.saturating_sub((limit as f64 * reserved).ceil() as usize)
+    }
+}
@@ -0,0 +1,477 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::client::RedisClient;
+use crate::{
+    build_status, duration_between, epoch_nanos, epoch_secs_to_system_time, system_time_to_epoch,
+    Clock, Error, Status, SystemClock, Tz, Window, RESERVED,
+};
+use futures::future::{self, Either, Loop};
+use futures::{Future, Stream};
+use redis::aio::Connection;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio_timer::Interval;
+
+pub use self::snapshot::{FileSnapshotStore, Snapshot, SnapshotStore};
+
+mod snapshot;
+
+/// The default limit of requests in a quota window
+const DEFAULT_LIMIT: usize = 1_000_000;
+/// The default length of a `Window::Period` quota window in seconds
+const DEFAULT_PERIOD_SECS: u64 = 60 * 60 * 24;
+/// The default prefix for the Redis keys holding quota windows
+const DEFAULT_PREFIX: &str = "limitation:quota";
+/// How long a window's hash is retained after the window ends, in seconds
+const RETENTION_SECS: i64 = 60 * 60 * 24;
+
+/// A long-running quota with durable consumption records, backed by Redis.
+///
+/// A [`Limiter`] keeps one short-lived counter per key which simply vanishes if Redis loses its
+/// data. That's fine for a window of a few minutes, but not for a monthly quota of millions of
+/// requests. A `Quota` instead records every key's consumption for a window in a single Redis
+/// hash, using only commands which replay faithfully from an append-only file (`HINCRBY` and an
+/// absolute `EXPIREAT`). The hash is kept for a day after its window ends. The window's generation
+/// is kept in a key of its own, so any key can be counted without colliding with it.
+///
+/// To survive a Redis server losing its data entirely (for example after a `FLUSHALL` or a
+/// restart without persistence), a `Quota` can periodically write a [`Snapshot`] of the current
+/// window to a [`SnapshotStore`], and [`restore`] the counters from the last snapshot.
+///
+/// Windows are calendar-aligned using the same [`Window`] modes as a `Limiter`. A
+/// `Window::Period` quota window is aligned to multiples of its period since the UNIX epoch.
+///
+/// # Example
+///
+/// ```no_run
+/// use futures::{Future, Stream};
+/// use limitation::{FileSnapshotStore, Quota, Window};
+/// use std::time::Duration;
+///
+/// let quota = Quota::build("redis://127.0.0.1/")
+///     .limit(10_000_000)
+///     .window(Window::Monthly)
+///     .snapshots(FileSnapshotStore::new("/var/lib/myapp/quota.snapshot"))
+///     .finish()?;
+///
+/// tokio::run(
+///     // Recover any counters lost since the last snapshot before serving requests
+///     quota
+///         .restore()
+///         .and_then(move |_restored| {
+///             quota
+///                 .snapshots_every(Duration::from_secs(60))
+///                 // Keep snapshotting even if one attempt fails
+///                 .then(|result| {
+///                     if let Err(err) = result {
+///                         eprintln!("quota snapshot failed: {}", err);
+///                     }
+///                     Ok::<(), limitation::Error>(())
+///                 })
+///                 .for_each(|_| Ok(()))
+///         })
+///         .map_err(|err| eprintln!("err: {}", err)),
+/// );
+/// # Ok::<(), limitation::Error>(())
+/// ```
+///
+/// [`Limiter`]: struct.Limiter.html
+/// [`restore`]: #method.restore
+/// [`Snapshot`]: struct.Snapshot.html
+/// [`SnapshotStore`]: trait.SnapshotStore.html
+/// [`Window`]: enum.Window.html
+#[derive(Clone, Debug)]
+pub struct Quota {
+    /// The Redis client
+    client: RedisClient,
+    /// Where snapshots are written to and read from
+    snapshots: Option<Arc<dyn SnapshotStore>>,
+    /// The source of the current time
+    clock: Arc<dyn Clock>,
+    /// The prefix for Redis keys
+    prefix: String,
+    /// The per-window limit
+    limit: usize,
+    /// The window length for `Window::Period` windows
+    period: Duration,
+    /// How windows are aligned
+    window: Window,
+    /// The time zone in which calendar windows are aligned
+    timezone: Tz,
+}
+
+impl Quota {
+    /// Returns a builder for a `Quota`.
+    pub fn build(redis_url: &str) -> QuotaBuilder<'_> {
+        QuotaBuilder::new(redis_url)
+    }
+
+    /// Counts a request on a key in the current window and returns a [`Status`].
+    ///
+    /// As with [`Limiter::count`], an `Error::LimitExceeded` containing a `Status` is returned if
+    /// the quota is exhausted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if:
+    ///
+    /// - The limit has been exceeded in the current window
+    /// - A client error has occurred
+    /// - A time computation failed
+    ///
+    /// [`Limiter::count`]: struct.Limiter.html#method.count
+    /// [`Status`]: struct.Status.html
+    pub fn count<K: Into<String>>(&self, key: K) -> impl Future<Item = Status, Error = Error> {
+        let key = key.into();
+        let limit = self.limit;
+        let now = self.clock.now();
+        let (start, end) = self.bounds(now);
+        let hash = self.window_key(start);
+        let generation_key = generation_key(&hash);
+        let expire_at = system_time_to_epoch(end) + RETENTION_SECS;
+        let generation = epoch_nanos(SystemTime::now());
+
+        self.client
+            .get_async_connection()
+            .from_err()
+            .and_then(move |con| {
+                let mut pipe = redis::pipe();
+                pipe.atomic()
+                    .cmd("SETNX")
+                    .arg(&generation_key)
+                    .arg(generation)
+                    .ignore()
+                    .cmd("HINCRBY")
+                    .arg(&hash)
+                    .arg(&key)
+                    .arg(1)
+                    .cmd("EXPIREAT")
+                    .arg(&hash)
+                    .arg(expire_at)
+                    .ignore()
+                    .cmd("EXPIREAT")
+                    .arg(&generation_key)
+                    .arg(expire_at)
+                    .ignore();
+
+                pipe.query_async(con)
+                    .from_err()
+                    .map(|(_, (count,)): (_, (usize,))| count)
+            })
+            .and_then(move |count| {
+                let status = build_status(
+                    count,
+                    limit,
+                    duration_between(start, end),
+                    now,
+                    duration_between(now, end),
+                )?;
+
+                if count > limit {
+                    Err(Error::LimitExceeded(status))
+                } else {
+                    Ok(status)
+                }
+            })
+    }
+
+    /// Reads the current window's counters from Redis and returns them as a [`Snapshot`].
+    ///
+    /// If a [`SnapshotStore`] was configured, the snapshot is also saved to it. Note that the
+    /// save is performed synchronously.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a client error has occurred or the snapshot fails to be saved.
+    ///
+    /// [`Snapshot`]: struct.Snapshot.html
+    /// [`SnapshotStore`]: trait.SnapshotStore.html
+    pub fn snapshot(&self) -> impl Future<Item = Snapshot, Error = Error> {
+        let (start, end) = self.bounds(self.clock.now());
+        let hash = self.window_key(start);
+        let store = self.snapshots.clone();
+
+        self.client
+            .get_async_connection()
+            .from_err()
+            .and_then(move |con| {
+                let mut pipe = redis::pipe();
+                pipe.atomic()
+                    .cmd("HGETALL")
+                    .arg(&hash)
+                    .cmd("GET")
+                    .arg(generation_key(&hash));
+
+                pipe.query_async(con).from_err()
+            })
+            .and_then(
+                move |(_, (fields, generation)): (_, (HashMap<String, u64>, Option<u64>))| {
+                    let mut snapshot = Snapshot::new(start, end, generation.unwrap_or(0));
+                    for (key, count) in fields {
+                        snapshot.insert(key, count as usize);
+                    }
+
+                    if let Some(store) = store {
+                        store.save(&snapshot)?;
+                    }
+
+                    Ok(snapshot)
+                },
+            )
+    }
+
+    /// Returns a `Stream` which takes a [`snapshot`] of the current window on every interval.
+    ///
+    /// A failed snapshot is yielded as an error without ending the stream, so the caller can
+    /// choose to report it and carry on. The stream must be run on a Tokio runtime with a timer.
+    ///
+    /// [`snapshot`]: #method.snapshot
+    pub fn snapshots_every(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Snapshot, Error = Error> {
+        let quota = self.clone();
+
+        Interval::new_interval(interval)
+            .from_err()
+            .and_then(move |_| quota.snapshot())
+    }
+
+    /// Restores the current window's counters from the last saved [`Snapshot`] after Redis has
+    /// lost them, and returns the number of keys which were restored.
+    ///
+    /// Each window records a generation, set by its first request, which is saved with every
+    /// snapshot. A window whose generation in Redis differs from the snapshot's was recreated
+    /// after the snapshot was taken, so the snapshot's counts are added on top of any requests
+    /// counted since then. Otherwise nothing was lost and nothing is restored, which makes it safe
+    /// to call this method every time a service starts. Requests counted between the last
+    /// snapshot and the data loss can't be recovered. The generation is checked and the counts
+    /// added in one optimistic transaction, so concurrent restores apply a snapshot only once.
+    ///
+    /// Nothing is restored if no [`SnapshotStore`] was configured, if no snapshot has been saved,
+    /// or if the saved snapshot is for an earlier window. The snapshot is loaded synchronously.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the snapshot fails to be loaded or a client error has occurred.
+    ///
+    /// [`Snapshot`]: struct.Snapshot.html
+    /// [`SnapshotStore`]: trait.SnapshotStore.html
+    pub fn restore(&self) -> impl Future<Item = usize, Error = Error> {
+        let (start, end) = self.bounds(self.clock.now());
+        let loaded = match self.snapshots {
+            Some(ref store) => store.load(),
+            None => Ok(None),
+        };
+        let snapshot = loaded.map(|snapshot| {
+            snapshot.filter(|snapshot| snapshot.window_start() == start && !snapshot.is_empty())
+        });
+        let hash = self.window_key(start);
+        let expire_at = system_time_to_epoch(end) + RETENTION_SECS;
+        let client = self.client.clone();
+
+        future::result(snapshot).and_then(move |snapshot| match snapshot {
+            None => Either::A(future::ok(0)),
+            Some(snapshot) => Either::B(client.get_async_connection().from_err().and_then(
+                move |con| {
+                    let snapshot = Arc::new(snapshot);
+
+                    future::loop_fn(con, move |con| {
+                        restore_once(con, hash.clone(), snapshot.clone(), expire_at)
+                    })
+                },
+            )),
+        })
+    }
+
+    /// Returns the start and end of the window containing `now`.
+    fn bounds(&self, now: SystemTime) -> (SystemTime, SystemTime) {
+        self.window.bounds(self.timezone, now).unwrap_or_else(|| {
+            let period = self.period.as_secs().max(1) as i64;
+            let start = system_time_to_epoch(now).div_euclid(period) * period;
+            (
+                epoch_secs_to_system_time(start),
+                epoch_secs_to_system_time(start + period),
+            )
+        })
+    }
+
+    /// Returns the Redis key of the hash for the window starting at `start`.
+    fn window_key(&self, start: SystemTime) -> String {
+        format!("{}:{}", self.prefix, system_time_to_epoch(start))
+    }
+}
+
+/// Returns the Redis key holding the generation of the window with the given hash.
+///
+/// The generation only needs to differ from any earlier generation of the same window, so it's
+/// set from the system time in nanoseconds regardless of the Quota's clock.
+fn generation_key(hash: &str) -> String {
+    format!("{}{}generation", hash, RESERVED)
+}
+
+/// Attempts to restore a snapshot's counts in a single optimistic transaction.
+///
+/// The window's hash and generation are watched while the generation is read, so a window
+/// recreated or restored concurrently aborts the transaction and the attempt is made again.
+fn restore_once(
+    con: Connection,
+    hash: String,
+    snapshot: Arc<Snapshot>,
+    expire_at: i64,
+) -> impl Future<Item = Loop<usize, Connection>, Error = Error> {
+    let generation_key = generation_key(&hash);
+
+    redis::cmd("WATCH")
+        .arg(&hash)
+        .arg(&generation_key)
+        .query_async(con)
+        .and_then({
+            let generation_key = generation_key.clone();
+            move |(con, ()): (_, ())| redis::cmd("GET").arg(&generation_key).query_async(con)
+        })
+        .and_then(move |(con, generation): (_, Option<u64>)| {
+            if generation == Some(snapshot.generation()) {
+                return Either::A(
+                    redis::cmd("UNWATCH")
+                        .query_async(con)
+                        .map(|(_, ()): (_, ())| Loop::Break(0)),
+                );
+            }
+
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            for (key, count) in snapshot.counts() {
+                pipe.cmd("HINCRBY").arg(&hash).arg(key).arg(count).ignore();
+            }
+            pipe.cmd("SET")
+                .arg(&generation_key)
+                .arg(snapshot.generation())
+                .ignore()
+                .cmd("EXPIREAT")
+                .arg(&hash)
+                .arg(expire_at)
+                .ignore()
+                .cmd("EXPIREAT")
+                .arg(&generation_key)
+                .arg(expire_at)
+                .ignore();
+
+            Either::B(
+                pipe.query_async(con).map(
+                    move |(con, committed): (_, Option<()>)| match committed {
+                        Some(()) => Loop::Break(snapshot.len()),
+                        None => Loop::Continue(con),
+                    },
+                ),
+            )
+        })
+        .from_err()
+}
+
+/// A builder for a [`Quota`].
+///
+/// [`Quota`]: struct.Quota.html
+pub struct QuotaBuilder<'a> {
+    redis_url: &'a str,
+    snapshots: Option<Arc<dyn SnapshotStore>>,
+    clock: Arc<dyn Clock>,
+    prefix: String,
+    limit: usize,
+    period: Duration,
+    window: Window,
+    timezone: Tz,
+}
+
+impl<'a> QuotaBuilder<'a> {
+    /// Creates a new `QuotaBuilder` with default settings.
+    fn new(redis_url: &'a str) -> Self {
+        QuotaBuilder {
+            redis_url,
+            snapshots: None,
+            clock: Arc::new(SystemClock),
+            prefix: DEFAULT_PREFIX.to_string(),
+            limit: DEFAULT_LIMIT,
+            period: Duration::from_secs(DEFAULT_PERIOD_SECS),
+            window: Window::Monthly,
+            timezone: Tz::UTC,
+        }
+    }
+
+    /// Sets a new maximum limit for each window. The default is `1000000`.
+    pub fn limit(&mut self, limit: usize) -> &mut Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets how the Quota's windows are aligned. The default is [`Window::Monthly`].
+    ///
+    /// [`Window::Monthly`]: enum.Window.html#variant.Monthly
+    pub fn window(&mut self, window: Window) -> &mut Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the window length for [`Window::Period`] windows. The default is 24 hours.
+    ///
+    /// [`Window::Period`]: enum.Window.html#variant.Period
+    pub fn period(&mut self, period: Duration) -> &mut Self {
+        self.period = period;
+        self
+    }
+
+    /// Sets the time zone in which calendar-aligned windows begin and end. The default is UTC.
+    pub fn timezone(&mut self, timezone: Tz) -> &mut Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Sets a new clock for the Quota. The default is a [`SystemClock`].
+    ///
+    /// [`SystemClock`]: struct.SystemClock.html
+    pub fn clock<C: Clock + 'static>(&mut self, clock: C) -> &mut Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets the prefix of the Redis keys holding each window's counters.
+    ///
+    /// The default is `"limitation:quota"`. Quotas with different limits or windows must use
+    /// different prefixes.
+    pub fn prefix<P: Into<String>>(&mut self, prefix: P) -> &mut Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sets where snapshots are saved to and restored from.
+    ///
+    /// Without a snapshot store, snapshots can still be taken but [`Quota::restore`] has nothing
+    /// to restore from.
+    ///
+    /// [`Quota::restore`]: struct.Quota.html#method.restore
+    pub fn snapshots<S: SnapshotStore + 'static>(&mut self, store: S) -> &mut Self {
+        self.snapshots = Some(Arc::new(store));
+        self
+    }
+
+    /// Finializes and returns a `Quota`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the Redis URL fails to parse.
+    pub fn finish(&self) -> Result<Quota, Error> {
+        Ok(Quota {
+            client: RedisClient::open(self.redis_url)?,
+            snapshots: self.snapshots.clone(),
+            clock: self.clock.clone(),
+            prefix: self.prefix.clone(),
+            limit: self.limit,
+            period: self.period,
+            window: self.window,
+            timezone: self.timezone,
+        })
+    }
+}
@@ -0,0 +1,234 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{epoch_secs_to_system_time, system_time_to_epoch, Error};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// The first line of a snapshot file, identifying its format
+const FILE_HEADER: &str = "limitation-quota-snapshot 1";
+
+/// The counters of every key in one quota window at a point in time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    window_start: SystemTime,
+    window_end: SystemTime,
+    generation: u64,
+    counts: BTreeMap<String, usize>,
+}
+
+impl Snapshot {
+    /// Creates a new, empty `Snapshot` of a window.
+    pub fn new(window_start: SystemTime, window_end: SystemTime, generation: u64) -> Self {
+        Snapshot {
+            window_start,
+            window_end,
+            generation,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the count for a key.
+    pub fn insert<K: Into<String>>(&mut self, key: K, count: usize) {
+        self.counts.insert(key.into(), count);
+    }
+
+    /// Returns the time at which the window began.
+    pub fn window_start(&self) -> SystemTime {
+        self.window_start
+    }
+
+    /// Returns the time at which the window ends.
+    pub fn window_end(&self) -> SystemTime {
+        self.window_end
+    }
+
+    /// Returns the generation of the window in Redis when the snapshot was taken.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the count for a key, which is zero if the key was not counted.
+    pub fn count(&self, key: &str) -> usize {
+        self.counts.get(key).cloned().unwrap_or(0)
+    }
+
+    /// Returns an iterator over every key and its count, ordered by key.
+    pub fn counts(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.counts
+            .iter()
+            .map(|(key, count)| (key.as_str(), *count))
+    }
+
+    /// Returns the number of keys in the snapshot.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Returns `true` if no keys were counted in the window.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+}
+
+/// Durable storage for the most recent [`Snapshot`] of a [`Quota`].
+///
+/// Implementations are called synchronously from the `Quota`'s futures and block the thread
+/// polling them, so they should be quick.
+///
+/// [`Quota`]: struct.Quota.html
+/// [`Snapshot`]: struct.Snapshot.html
+pub trait SnapshotStore: fmt::Debug + Send + Sync {
+    /// Saves a snapshot, replacing any previously saved snapshot.
+    fn save(&self, snapshot: &Snapshot) -> Result<(), Error>;
+
+    /// Loads the most recently saved snapshot, if there is one.
+    fn load(&self) -> Result<Option<Snapshot>, Error>;
+}
+
+/// A `SnapshotStore` which keeps the latest snapshot in a local file.
+///
+/// The snapshot is written to a temporary file alongside the target which is then renamed over
+/// it, so a crash while saving leaves the previous snapshot intact. The file is a line-oriented
+/// text format: a header, the window, and then one line per key holding its count and the key.
+///
+/// Reading and writing the file blocks the calling thread, which is the thread polling the
+/// `Quota`'s futures, so snapshots should be small and infrequent, such as one a minute for a
+/// quota with a few thousand keys, or taken on a runtime which can afford to block.
+#[derive(Clone, Debug)]
+pub struct FileSnapshotStore {
+    /// The path of the snapshot file
+    path: PathBuf,
+}
+
+impl FileSnapshotStore {
+    /// Creates a new `FileSnapshotStore` which saves to the given path.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileSnapshotStore { path: path.into() }
+    }
+
+    /// Writes the snapshot to a temporary file and renames it over the target.
+    fn write(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        {
+            let mut file = BufWriter::new(fs::File::create(&tmp)?);
+            writeln!(file, "{}", FILE_HEADER)?;
+            writeln!(
+                file,
+                "window {} {} {}",
+                system_time_to_epoch(snapshot.window_start),
+                system_time_to_epoch(snapshot.window_end),
+                snapshot.generation
+            )?;
+            for (key, count) in snapshot.counts() {
+                writeln!(file, "{} {}", count, escape(key))?;
+            }
+            file.into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()?;
+        }
+
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Reads the snapshot file, if one exists.
+    fn read(&self) -> io::Result<Option<Snapshot>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let mut lines = BufReader::new(file).lines();
+
+        if lines.next().transpose()?.as_deref() != Some(FILE_HEADER) {
+            return Err(invalid("unrecognized snapshot header"));
+        }
+        let window = lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| invalid("missing snapshot window"))?;
+        let mut fields = window.split(' ');
+        if fields.next() != Some("window") {
+            return Err(invalid("missing snapshot window"));
+        }
+        let mut next_number = || {
+            fields
+                .next()
+                .and_then(|field| field.parse::<i64>().ok())
+                .ok_or_else(|| invalid("invalid snapshot window"))
+        };
+        let mut snapshot = Snapshot::new(
+            epoch_secs_to_system_time(next_number()?),
+            epoch_secs_to_system_time(next_number()?),
+            next_number()? as u64,
+        );
+
+        for line in lines {
+            let line = line?;
+            let mut fields = line.splitn(2, ' ');
+            let count = fields
+                .next()
+                .and_then(|field| field.parse().ok())
+                .ok_or_else(|| invalid("invalid snapshot count"))?;
+            let key = fields
+                .next()
+                .and_then(unescape)
+                .ok_or_else(|| invalid("invalid snapshot key"))?;
+            snapshot.insert(key, count);
+        }
+
+        Ok(Some(snapshot))
+    }
+}
+
+impl SnapshotStore for FileSnapshotStore {
+    fn save(&self, snapshot: &Snapshot) -> Result<(), Error> {
+        self.write(snapshot).map_err(Error::Snapshot)
+    }
+
+    fn load(&self) -> Result<Option<Snapshot>, Error> {
+        self.read().map_err(Error::Snapshot)
+    }
+}
+
+/// Returns an error for a malformed snapshot file.
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Escapes backslashes and line breaks so a key fits on one line.
+fn escape(key: &str) -> String {
+    key.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Reverses `escape`, returning `None` for an invalid escape sequence.
+fn unescape(escaped: &str) -> Option<String> {
+    let mut key = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next()? {
+                '\\' => key.push('\\'),
+                'n' => key.push('\n'),
+                'r' => key.push('\r'),
+                _ => return None,
+            }
+        } else {
+            key.push(c);
+        }
+    }
+
+    Some(key)
+}
@@ -0,0 +1,207 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{ConcurrencyStatus, Error, Status};
+use serde::{Deserialize, Serialize};
+
+/// A serializable representation of an [`Error`].
+///
+/// An `Error` can't be serialized directly as it may wrap errors from other crates, so an
+/// `ErrorReport` captures the kind of error, a human readable message, and the [`Status`] if the
+/// limit was exceeded. When serialized to JSON, a limit exceeded error looks like:
+///
+/// ```json
+/// {
+///   "kind": "limit_exceeded",
+///   "message": "rate limit exceeded",
+///   "status": {
+///     "limit": 5,
+///     "remaining": 0,
+///     "count": 6,
+///     "window_start_epoch_utc": 1571600000,
+///     "reset_epoch_utc": 1571600060,
+///     "retry_after_ms": 42000
+///   }
+/// }
+/// ```
+///
+/// The `status` field is omitted for other kinds of errors. A concurrency exceeded error instead
+/// has a `concurrency` field holding the [`ConcurrencyStatus`]:
+///
+/// ```json
+/// {
+///   "kind": "concurrency_exceeded",
+///   "message": "concurrency limit exceeded",
+///   "concurrency": {
+///     "max": 2,
+///     "in_flight": 2
+///   }
+/// }
+/// ```
+///
+/// # Example
+///
+/// ```
+/// use limitation::{Error, ErrorReport};
+///
+/// fn report(err: &Error) -> ErrorReport {
+///     ErrorReport::from(err)
+/// }
+/// ```
+///
+/// [`ConcurrencyStatus`]: struct.ConcurrencyStatus.html
+/// [`Error`]: enum.Error.html
+/// [`Status`]: struct.Status.html
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorReport {
+    kind: ErrorKind,
+    message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<Status>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    concurrency: Option<ConcurrencyStatus>,
+}
+
+impl ErrorReport {
+    /// Returns the kind of error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns a human readable description of the error.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the `Status` if the limit was exceeded.
+    pub fn status(&self) -> Option<&Status> {
+        self.status.as_ref()
+    }
+
+    /// Returns the `ConcurrencyStatus` if the maximum number of requests in flight was reached.
+    pub fn concurrency(&self) -> Option<&ConcurrencyStatus> {
+        self.concurrency.as_ref()
+    }
+}
+
+impl<'a> From<&'a Error> for ErrorReport {
+    fn from(err: &'a Error) -> Self {
+        match err {
+            Error::Auth(_) => ErrorReport {
+                kind: ErrorKind::Auth,
+                message: err.to_string(),
+                status: None,
+                concurrency: None,
+            },
+            Error::Client(_) => ErrorReport {
+                kind: ErrorKind::Client,
+                message: err.to_string(),
+                status: None,
+                concurrency: None,
+            },
+            Error::ConcurrencyExceeded(concurrency) => ErrorReport {
+                kind: ErrorKind::ConcurrencyExceeded,
+                message: "concurrency limit exceeded".to_string(),
+                status: None,
+                concurrency: Some(concurrency.clone()),
+            },
+            Error::Config(_) => ErrorReport {
+                kind: ErrorKind::Config,
+                message: err.to_string(),
+                status: None,
+                concurrency: None,
+            },
+            Error::Event(_) => ErrorReport {
+                kind: ErrorKind::Event,
+                message: err.to_string(),
+                status: None,
+                concurrency: None,
+            },
+            Error::LimitExceeded(status) => ErrorReport {
+                kind: ErrorKind::LimitExceeded,
+                message: "rate limit exceeded".to_string(),
+                status: Some(status.clone()),
+                concurrency: None,
+            },
+            Error::Snapshot(_) => ErrorReport {
+                kind: ErrorKind::Snapshot,
+                message: err.to_string(),
+                status: None,
+                concurrency: None,
+            },
+            Error::Store(_) => ErrorReport {
+                kind: ErrorKind::Client,
+                message: err.to_string(),
+                status: None,
+                concurrency: None,
+            },
+            Error::Time(_) => ErrorReport {
+                kind: ErrorKind::Time,
+                message: err.to_string(),
+                status: None,
+                concurrency: None,
+            },
+            Error::Timeout(_) => ErrorReport {
+                kind: ErrorKind::Timeout,
+                message: err.to_string(),
+                status: None,
+                concurrency: None,
+            },
+            Error::Timer(_) => ErrorReport {
+                kind: ErrorKind::Timer,
+                message: err.to_string(),
+                status: None,
+                concurrency: None,
+            },
+        }
+    }
+}
+
+/// The kind of an [`ErrorReport`], serialized as a `snake_case` string.
+///
+/// [`ErrorReport`]: struct.ErrorReport.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The backend refused the credentials given, or required credentials which weren't given.
+    Auth,
+    /// The backend failed to connect or run a query.
+    Client,
+    /// The maximum number of requests in flight is reached for a key.
+    ConcurrencyExceeded,
+    /// A configuration value is invalid.
+    Config,
+    /// A published event failed to be decoded.
+    Event,
+    /// The limit is exceeded for a key.
+    LimitExceeded,
+    /// A quota snapshot failed to be saved or loaded.
+    Snapshot,
+    /// A time conversion failed.
+    Time,
+    /// A decision took longer than its timeout.
+    Timeout,
+    /// The timer failed.
+    Timer,
+}
+
+/// (De)serializes a `Duration` as a whole number of milliseconds.
+pub(crate) mod duration_millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::convert::TryInto;
+    use std::time::Duration;
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis().try_into().unwrap_or(u64::MAX))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+}
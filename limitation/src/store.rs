@@ -0,0 +1,114 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::Error;
+use futures::{future, Future};
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "memcached")]
+mod memcached;
+mod memory;
+mod redis;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "memcached")]
+pub use self::memcached::MemcachedStore;
+pub use self::memory::MemoryStore;
+pub use self::redis::RedisStore;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteStore;
+
+/// A boxed Future returned by `Store` operations.
+pub type StoreFuture<T> = Box<dyn Future<Item = T, Error = Error> + Send>;
+
+/// The counts and times remaining for several keys, which are `Ok` if the keys were counted and
+/// `Err` if counting would have taken any key over its limit.
+pub type Tracked = Result<Vec<(usize, Duration)>, Vec<(usize, Duration)>>;
+
+/// A persistence backend which tracks fixed window counters for a [`Limiter`].
+///
+/// The default backend is a [`RedisStore`] which is used when building a `Limiter` with a Redis
+/// URL. Other implementations can be provided with [`Limiter::build_with_store`].
+///
+/// [`Limiter`]: struct.Limiter.html
+/// [`Limiter::build_with_store`]: struct.Limiter.html#method.build_with_store
+/// [`RedisStore`]: struct.RedisStore.html
+pub trait Store: fmt::Debug + Send + Sync {
+    /// Counts one request on a key and returns the count and time remaining in its window.
+    ///
+    /// This is equivalent to calling [`track_by`] with an amount of `1`.
+    ///
+    /// [`track_by`]: #tymethod.track_by
+    fn track(
+        &self,
+        key: String,
+        period: Duration,
+        now: SystemTime,
+    ) -> StoreFuture<(usize, Duration)> {
+        self.track_by(key, 1, period, now)
+    }
+
+    /// Counts `amount` requests on a key and returns the count and time remaining in its window.
+    ///
+    /// If the key has no current window, a new window of length `period` begins at `now`. A store
+    /// which manages its own expiry (such as Redis) may disregard `now`.
+    fn track_by(
+        &self,
+        key: String,
+        amount: usize,
+        period: Duration,
+        now: SystemTime,
+    ) -> StoreFuture<(usize, Duration)>;
+
+    /// Counts `amount` requests on each of several keys, unless any key would go over its limit,
+    /// and returns the count and time remaining in each key's window, in the same order as the
+    /// keys.
+    ///
+    /// Each key is given with its period and limit, and is tracked as by [`track_by`]. If counting
+    /// would take any key over its limit then no key is counted, and the counts each key would
+    /// have reached are returned as the `Err` of the inner `Result`, which a store mustn't return
+    /// for any other reason. A store should check and count every key in one atomic step where it
+    /// can, so that no other request is counted part way through and no key is counted and then
+    /// given back.
+    ///
+    /// [`track_by`]: #tymethod.track_by
+    fn track_all_within(
+        &self,
+        keys: Vec<(String, Duration, usize)>,
+        amount: usize,
+        now: SystemTime,
+    ) -> StoreFuture<Tracked>;
+
+    /// Gives back `amount` previously tracked requests to a key's current window.
+    ///
+    /// This is used to return quota which was tracked ahead of time but went unused. If the key's
+    /// window has already ended then there is nothing to give back, and the store must not start
+    /// a new window. Releasing is best-effort, so a store may also do nothing if it can't safely
+    /// give the requests back.
+    fn release(&self, key: String, amount: usize, now: SystemTime) -> StoreFuture<()>;
+
+    /// Returns the count and time remaining in a key's current window without counting a
+    /// request, or `None` if the key has no current window.
+    ///
+    /// A store must not start a new window when peeking.
+    fn peek(&self, key: String, now: SystemTime) -> StoreFuture<Option<(usize, Duration)>>;
+
+    /// Returns the name, count, and time remaining of every key with a current window whose name
+    /// begins with `prefix`, in no particular order.
+    ///
+    /// This is used to report on usage, so a store should list its keys a batch at a time rather
+    /// than holding up the requests being counted. The default implementation returns an error, as
+    /// not every store can list its keys.
+    fn scan(
+        &self,
+        _prefix: String,
+        _now: SystemTime,
+    ) -> StoreFuture<Vec<(String, usize, Duration)>> {
+        Box::new(future::err(Error::Config(
+            "store does not support listing keys".to_string(),
+        )))
+    }
+}
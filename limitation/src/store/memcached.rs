@@ -0,0 +1,440 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{Store, StoreFuture, Tracked};
+use crate::{ceil_secs, duration_between, epoch_millis, trace, Error};
+use futures::future::{self, Either, Loop};
+use futures::Future;
+use std::fmt::Write;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::str;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio_io::io as aio;
+use tokio_tcp::TcpStream;
+
+/// The longest key memcached accepts, in bytes
+const MAX_KEY_LEN: usize = 250;
+/// The longest expiry memcached treats as relative; anything longer is read as a UNIX timestamp
+const MAX_RELATIVE_EXPIRY: u64 = 60 * 60 * 24 * 30;
+/// The prefix of the companion key which holds the end of a key's window
+///
+/// A `%` in a key is always encoded, followed by two hex digits, so no encoded key starts with
+/// this prefix.
+const RESET_PREFIX: &str = "%reset:";
+/// The number of bytes read from the server at a time
+const READ_SIZE: usize = 4096;
+
+/// A `Store` backed by a memcached server.
+///
+/// Windows are reproduced with memcached's `add` and `incr` commands: a counter is added with an
+/// expiry of one period if it doesn't exist, then incremented. Memcached can't report how long a
+/// key has left to live, so the end of each window is added alongside its counter in a companion
+/// key with the same expiry, named for the key with a `%reset:` prefix. The end of a window is
+/// taken from the time passed to [`track_by`], while expiry is left to the server.
+///
+/// Keys are percent-encoded where they contain spaces, control characters, or percent signs,
+/// which memcached doesn't allow or which would clash with companion keys. A key longer than
+/// memcached's limit of 250 bytes once encoded, with its companion key's prefix, is an error.
+///
+/// Memcached has no transactions, so [`track_all_within`] checks every key and then tracks them
+/// one after another, which lets concurrent requests each take the last of a limit. It can't list
+/// its keys either, so [`Limiter::usage`] isn't supported.
+///
+/// [`Limiter::usage`]: struct.Limiter.html#method.usage
+/// [`track_all_within`]: trait.Store.html#tymethod.track_all_within
+/// [`track_by`]: trait.Store.html#tymethod.track_by
+#[derive(Clone, Debug)]
+pub struct MemcachedStore {
+    /// The address of the memcached server
+    addr: SocketAddr,
+}
+
+/// A reply to a memcached command.
+#[derive(Debug)]
+enum Reply {
+    Stored,
+    NotStored,
+    NotFound,
+    Number(u64),
+    Values(Vec<(String, Vec<u8>)>),
+}
+
+impl MemcachedStore {
+    /// Creates a new `MemcachedStore` for a server at the given address, such as
+    /// `"127.0.0.1:11211"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the address fails to resolve.
+    pub fn open(addr: &str) -> Result<Self, Error> {
+        let addr = addr
+            .to_socket_addrs()
+            .map_err(|err| Error::Store(Box::new(err)))?
+            .next()
+            .ok_or_else(|| Error::Config(format!("address {} did not resolve", addr)))?;
+
+        Ok(MemcachedStore { addr })
+    }
+}
+
+impl Store for MemcachedStore {
+    fn track_by(
+        &self,
+        key: String,
+        amount: usize,
+        period: Duration,
+        now: SystemTime,
+    ) -> StoreFuture<(usize, Duration)> {
+        let key = match encode_key(&key) {
+            Ok(key) => key,
+            Err(err) => return Box::new(future::err(err)),
+        };
+        let reset = epoch_millis(now + period).to_string();
+        let exptime = exptime(now, period);
+        let started = Instant::now();
+
+        let mut request = String::new();
+        let _ = write!(
+            request,
+            "add {prefix}{key} 0 {exptime} {len}\r\n{reset}\r\n\
+             add {key} 0 {exptime} 1\r\n0\r\n\
+             incr {key} {amount}\r\n\
+             get {prefix}{key}\r\n",
+            key = key,
+            prefix = RESET_PREFIX,
+            exptime = exptime,
+            len = reset.len(),
+            reset = reset,
+            amount = amount,
+        );
+
+        let future = round_trip(self.addr, request, 4)
+            .and_then(move |replies| match (&replies[2], &replies[3]) {
+                (Reply::Number(count), Reply::Values(values)) => {
+                    // Without a companion key the window has just ended, so report a full period
+                    let ttl = values
+                        .first()
+                        .and_then(|(_, value)| parse_number(value))
+                        .and_then(|reset| remaining(now, reset))
+                        .unwrap_or(period);
+
+                    Ok((*count as usize, ttl))
+                }
+                (Reply::NotFound, _) => Err(protocol_error("key expired while tracking")),
+                _ => Err(unexpected(&replies)),
+            })
+            .then(move |result| {
+                trace::memcached_result(&result, started.elapsed());
+                result
+            });
+
+        Box::new(trace::instrument(
+            future,
+            trace::memcached_span("add, add, incr, get"),
+        ))
+    }
+
+    fn track_all_within(
+        &self,
+        keys: Vec<(String, Duration, usize)>,
+        amount: usize,
+        now: SystemTime,
+    ) -> StoreFuture<Tracked> {
+        let store = self.clone();
+        let peeks: Vec<_> = keys
+            .iter()
+            .map(|(key, _, _)| self.peek(key.clone(), now))
+            .collect();
+
+        Box::new(future::join_all(peeks).and_then(move |current| {
+            let reached: Vec<_> = keys
+                .iter()
+                .zip(current)
+                .map(|((_, period, _), current)| {
+                    current.map_or((amount, *period), |(count, ttl)| (count + amount, ttl))
+                })
+                .collect();
+            if keys
+                .iter()
+                .zip(&reached)
+                .any(|((_, _, limit), (count, _))| count > limit)
+            {
+                return Either::A(future::ok(Err(reached)));
+            }
+
+            let tracks: Vec<_> = keys
+                .into_iter()
+                .map(|(key, period, _)| store.track_by(key, amount, period, now))
+                .collect();
+
+            Either::B(future::join_all(tracks).map(Ok))
+        }))
+    }
+
+    fn release(&self, key: String, amount: usize, _now: SystemTime) -> StoreFuture<()> {
+        let key = match encode_key(&key) {
+            Ok(key) => key,
+            Err(err) => return Box::new(future::err(err)),
+        };
+        let started = Instant::now();
+
+        // `decr` never creates a key, so a window which has ended isn't started again
+        let future = round_trip(self.addr, format!("decr {} {}\r\n", key, amount), 1)
+            .and_then(|replies| match replies[0] {
+                Reply::Number(_) | Reply::NotFound => Ok(()),
+                _ => Err(unexpected(&replies)),
+            })
+            .then(move |result| {
+                trace::memcached_result(&result, started.elapsed());
+                result
+            });
+
+        Box::new(trace::instrument(future, trace::memcached_span("decr")))
+    }
+
+    fn peek(&self, key: String, now: SystemTime) -> StoreFuture<Option<(usize, Duration)>> {
+        let key = match encode_key(&key) {
+            Ok(key) => key,
+            Err(err) => return Box::new(future::err(err)),
+        };
+        let reset_key = format!("{}{}", RESET_PREFIX, key);
+        let started = Instant::now();
+
+        let request = format!("get {} {}\r\n", key, reset_key);
+        let future = round_trip(self.addr, request, 1)
+            .and_then(move |replies| match replies[0] {
+                Reply::Values(ref values) => {
+                    let value = |name: &str| {
+                        values
+                            .iter()
+                            .find(|(found, _)| found == name)
+                            .and_then(|(_, value)| parse_number(value))
+                    };
+
+                    // A counter without a future reset has either expired or was never tracked
+                    Ok(value(&key).and_then(|count| {
+                        value(&reset_key)
+                            .and_then(|reset| remaining(now, reset))
+                            .map(|ttl| (count as usize, ttl))
+                    }))
+                }
+                _ => Err(unexpected(&replies)),
+            })
+            .then(move |result| {
+                trace::memcached_result(&result, started.elapsed());
+                result
+            });
+
+        Box::new(trace::instrument(future, trace::memcached_span("get")))
+    }
+}
+
+/// Sends a request of one or more commands to the server on a new connection and reads the given
+/// number of replies.
+fn round_trip(
+    addr: SocketAddr,
+    request: String,
+    replies: usize,
+) -> impl Future<Item = Vec<Reply>, Error = Error> {
+    TcpStream::connect(&addr)
+        .and_then(move |stream| aio::write_all(stream, request))
+        .and_then(move |(stream, _)| {
+            future::loop_fn((stream, Vec::new()), move |(stream, mut buf)| {
+                aio::read(stream, vec![0; READ_SIZE]).and_then(move |(stream, chunk, read)| {
+                    if read == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "connection closed before all replies were read",
+                        ));
+                    }
+                    buf.extend_from_slice(&chunk[..read]);
+
+                    match parse_replies(&buf, replies)? {
+                        Some(parsed) => Ok(Loop::Break(parsed)),
+                        None => Ok(Loop::Continue((stream, buf))),
+                    }
+                })
+            })
+        })
+        .map_err(|err| Error::Store(Box::new(err)))
+}
+
+/// Parses the given number of replies from the start of a buffer, or returns `None` if the buffer
+/// doesn't yet hold them all.
+///
+/// An error reply from the server fails the whole request.
+fn parse_replies(buf: &[u8], count: usize) -> io::Result<Option<Vec<Reply>>> {
+    let mut replies = Vec::with_capacity(count);
+    let mut rest = buf;
+
+    while replies.len() < count {
+        match parse_reply(rest)? {
+            Some((reply, remaining)) => {
+                replies.push(reply);
+                rest = remaining;
+            }
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(replies))
+}
+
+/// Parses one reply from the start of a buffer and returns it with the rest of the buffer, or
+/// returns `None` if the buffer doesn't yet hold all of it.
+fn parse_reply(buf: &[u8]) -> io::Result<Option<(Reply, &[u8])>> {
+    let (line, mut rest) = match split_line(buf)? {
+        Some(split) => split,
+        None => return Ok(None),
+    };
+
+    let reply = match line {
+        "STORED" => Reply::Stored,
+        "NOT_STORED" => Reply::NotStored,
+        "NOT_FOUND" => Reply::NotFound,
+        "END" => Reply::Values(Vec::new()),
+        _ if line.starts_with("VALUE ") => {
+            let mut values = Vec::new();
+            let mut header = line;
+
+            loop {
+                let (key, len) = parse_value_header(header)?;
+                if rest.len() < len + 2 {
+                    return Ok(None);
+                }
+                if &rest[len..len + 2] != b"\r\n" {
+                    return Err(invalid_data("value is longer than its header"));
+                }
+                values.push((key.to_string(), rest[..len].to_vec()));
+                rest = &rest[len + 2..];
+
+                match split_line(rest)? {
+                    Some(("END", remaining)) => {
+                        rest = remaining;
+                        break;
+                    }
+                    Some((next, remaining)) if next.starts_with("VALUE ") => {
+                        header = next;
+                        rest = remaining;
+                    }
+                    Some((other, _)) => {
+                        return Err(invalid_data(&format!("unexpected reply: {}", other)))
+                    }
+                    None => return Ok(None),
+                }
+            }
+
+            Reply::Values(values)
+        }
+        _ if line.starts_with("SERVER_ERROR") || line.starts_with("CLIENT_ERROR") => {
+            return Err(io::Error::new(io::ErrorKind::Other, line.to_string()))
+        }
+        "ERROR" => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "command not recognized",
+            ))
+        }
+        _ => match line.parse() {
+            Ok(number) => Reply::Number(number),
+            Err(_) => return Err(invalid_data(&format!("unexpected reply: {}", line))),
+        },
+    };
+
+    Ok(Some((reply, rest)))
+}
+
+/// Splits the first line from a buffer, or returns `None` if the buffer doesn't yet hold a whole
+/// line.
+fn split_line(buf: &[u8]) -> io::Result<Option<(&str, &[u8])>> {
+    match buf.windows(2).position(|window| window == b"\r\n") {
+        Some(end) => {
+            let line = str::from_utf8(&buf[..end])
+                .map_err(|_| invalid_data("reply line is not valid UTF-8"))?;
+            Ok(Some((line, &buf[end + 2..])))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Parses the key and length from a `VALUE <key> <flags> <bytes> [<cas>]` line.
+fn parse_value_header(line: &str) -> io::Result<(&str, usize)> {
+    let mut parts = line.split(' ').skip(1);
+    let key = parts.next();
+    let len = parts.nth(1).and_then(|len| len.parse().ok());
+
+    match (key, len) {
+        (Some(key), Some(len)) => Ok((key, len)),
+        _ => Err(invalid_data(&format!("invalid value header: {}", line))),
+    }
+}
+
+/// Parses a stored value as a decimal number.
+fn parse_number(value: &[u8]) -> Option<u64> {
+    str::from_utf8(value).ok()?.trim().parse().ok()
+}
+
+/// Percent-encodes the spaces, control characters, and percent signs in a key, which memcached
+/// would otherwise reject or misread.
+///
+/// Returns an error if the key, with its companion key prefix, is too long for memcached.
+fn encode_key(key: &str) -> Result<String, Error> {
+    let mut encoded = String::with_capacity(key.len());
+    for c in key.chars() {
+        if c <= ' ' || c == '\u{7f}' || c == '%' {
+            let _ = write!(encoded, "%{:02X}", c as u8);
+        } else {
+            encoded.push(c);
+        }
+    }
+
+    if RESET_PREFIX.len() + encoded.len() > MAX_KEY_LEN {
+        return Err(Error::Store(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("key is longer than {} bytes once encoded", MAX_KEY_LEN),
+        ))));
+    }
+
+    Ok(encoded)
+}
+
+/// Returns the expiry of a window of `period` starting at `now`, in whole seconds.
+///
+/// Memcached reads an expiry longer than 30 days as a UNIX timestamp, so longer windows expire at
+/// their end instead.
+fn exptime(now: SystemTime, period: Duration) -> u64 {
+    let secs = ceil_secs(period).as_secs().max(1);
+    if secs > MAX_RELATIVE_EXPIRY {
+        ceil_secs(duration_between(UNIX_EPOCH, now + period)).as_secs()
+    } else {
+        secs
+    }
+}
+
+/// Returns the time remaining from `now` until a window's end, given as a UNIX timestamp in
+/// milliseconds, or `None` if the window has ended.
+fn remaining(now: SystemTime, reset: u64) -> Option<Duration> {
+    let ttl = duration_between(now, UNIX_EPOCH + Duration::from_millis(reset));
+    if ttl > Duration::from_secs(0) {
+        Some(ttl)
+    } else {
+        None
+    }
+}
+
+/// Returns an error for replies which don't match the commands sent.
+fn unexpected(replies: &[Reply]) -> Error {
+    protocol_error(&format!("unexpected replies: {:?}", replies))
+}
+
+/// Returns a store error for a reply which couldn't be understood.
+fn protocol_error(msg: &str) -> Error {
+    Error::Store(Box::new(invalid_data(msg)))
+}
+
+/// Returns an I/O error for data which couldn't be understood.
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
@@ -0,0 +1,159 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{Store, StoreFuture, Tracked};
+use crate::duration_between;
+use futures::future;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// A `Store` which keeps its counters in process memory.
+///
+/// Windows are expired using the time passed to [`track_by`], which comes from the `Limiter`'s
+/// [`Clock`], so pairing this store with a [`ManualClock`] makes window boundaries fully
+/// deterministic. Counters are not shared between processes and expired windows are only
+/// reclaimed when their key is next tracked, so this store is best suited to testing and
+/// single-process use.
+///
+/// Clones of a `MemoryStore` share the same counters.
+///
+/// [`Clock`]: trait.Clock.html
+/// [`ManualClock`]: struct.ManualClock.html
+/// [`track_by`]: trait.Store.html#tymethod.track_by
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    windows: Arc<Mutex<HashMap<String, Window>>>,
+}
+
+/// A counter for a key and the time its window ends.
+#[derive(Clone, Copy, Debug)]
+struct Window {
+    count: usize,
+    expires_at: SystemTime,
+}
+
+impl MemoryStore {
+    /// Creates a new, empty `MemoryStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn track_by(
+        &self,
+        key: String,
+        amount: usize,
+        period: Duration,
+        now: SystemTime,
+    ) -> StoreFuture<(usize, Duration)> {
+        let mut windows = self.windows.lock().expect("store lock was poisoned");
+
+        let tracked = track_window(&mut windows, key, amount, period, now);
+
+        Box::new(future::ok(tracked))
+    }
+
+    fn track_all_within(
+        &self,
+        keys: Vec<(String, Duration, usize)>,
+        amount: usize,
+        now: SystemTime,
+    ) -> StoreFuture<Tracked> {
+        let mut windows = self.windows.lock().expect("store lock was poisoned");
+
+        let reached: Vec<_> = keys
+            .iter()
+            .map(|(key, period, _)| {
+                windows
+                    .get(key)
+                    .filter(|window| window.expires_at > now)
+                    .map_or((amount, *period), |window| {
+                        (
+                            window.count + amount,
+                            duration_between(now, window.expires_at),
+                        )
+                    })
+            })
+            .collect();
+        if keys
+            .iter()
+            .zip(&reached)
+            .any(|((_, _, limit), (count, _))| count > limit)
+        {
+            return Box::new(future::ok(Err(reached)));
+        }
+
+        let tracked = keys
+            .into_iter()
+            .map(|(key, period, _)| track_window(&mut windows, key, amount, period, now))
+            .collect();
+
+        Box::new(future::ok(Ok(tracked)))
+    }
+
+    fn release(&self, key: String, amount: usize, now: SystemTime) -> StoreFuture<()> {
+        let mut windows = self.windows.lock().expect("store lock was poisoned");
+
+        if let Some(window) = windows.get_mut(&key) {
+            if window.expires_at > now {
+                window.count = window.count.saturating_sub(amount);
+            }
+        }
+
+        Box::new(future::ok(()))
+    }
+
+    fn peek(&self, key: String, now: SystemTime) -> StoreFuture<Option<(usize, Duration)>> {
+        let windows = self.windows.lock().expect("store lock was poisoned");
+
+        let current = windows
+            .get(&key)
+            .filter(|window| window.expires_at > now)
+            .map(|window| (window.count, duration_between(now, window.expires_at)));
+
+        Box::new(future::ok(current))
+    }
+
+    fn scan(&self, prefix: String, now: SystemTime) -> StoreFuture<Vec<(String, usize, Duration)>> {
+        let windows = self.windows.lock().expect("store lock was poisoned");
+
+        let found = windows
+            .iter()
+            .filter(|(key, window)| key.starts_with(&prefix) && window.expires_at > now)
+            .map(|(key, window)| {
+                (
+                    key.clone(),
+                    window.count,
+                    duration_between(now, window.expires_at),
+                )
+            })
+            .collect();
+
+        Box::new(future::ok(found))
+    }
+}
+
+/// Counts `amount` requests on a key's window, starting a new window if it has ended, and returns
+/// the count and time remaining.
+fn track_window(
+    windows: &mut HashMap<String, Window>,
+    key: String,
+    amount: usize,
+    period: Duration,
+    now: SystemTime,
+) -> (usize, Duration) {
+    let window = windows.entry(key).or_insert(Window {
+        count: 0,
+        expires_at: now + period,
+    });
+    if window.expires_at <= now {
+        window.count = 0;
+        window.expires_at = now + period;
+    }
+    window.count += amount;
+
+    (window.count, duration_between(now, window.expires_at))
+}
@@ -0,0 +1,367 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{Store, StoreFuture, Tracked};
+use crate::client::{self, RedisClient};
+use crate::{trace, Error};
+use futures::future::{self, Either, Loop};
+use futures::Future;
+use redis::Value;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The number of keys asked for in each `SCAN` when listing keys
+const SCAN_COUNT: usize = 100;
+/// Counts a request on several keys, unless any key would go over its limit.
+///
+/// `KEYS` are the keys to count on, and the arguments are the amount to count followed by the
+/// period (in seconds) and limit of each key. Returns whether the keys were counted, followed by
+/// the count each key reached or would have reached and the time remaining in its window (in
+/// seconds).
+const TRACK_WITHIN_SCRIPT: &str = r"
+local amount = tonumber(ARGV[1])
+local reached = {1}
+local started = {}
+for i, key in ipairs(KEYS) do
+    local count = redis.call('GET', key)
+    local ttl = redis.call('TTL', key)
+    -- A key without a TTL has either expired or was never tracked
+    if count and ttl > 0 then
+        count = tonumber(count) + amount
+    else
+        count = amount
+        ttl = tonumber(ARGV[2 * i])
+        started[i] = true
+    end
+    if count > tonumber(ARGV[2 * i + 1]) then
+        reached[1] = 0
+    end
+    table.insert(reached, count)
+    table.insert(reached, ttl)
+end
+if reached[1] == 1 then
+    for i, key in ipairs(KEYS) do
+        if started[i] then
+            redis.call('SET', key, amount, 'EX', ARGV[2 * i])
+        else
+            redis.call('INCRBY', key, amount)
+        end
+    end
+end
+return reached
+";
+
+/// A `Store` backed by a Redis server.
+///
+/// Window expiry is delegated to Redis by setting a TTL on each key, so the time passed to
+/// [`track_by`] is not used.
+///
+/// Released requests are given back with an optimistic transaction (`WATCH` and `MULTI`), so that
+/// a window which expires at the same moment isn't recreated without a TTL. If the key changes
+/// while releasing, the release is abandoned. Several keys are checked against their limits and
+/// tracked by a Lua script, which the server runs atomically.
+///
+/// Keys are listed with `SCAN`, a batch at a time, so listing a large number of keys doesn't block
+/// the server. A key which is created or expires while listing may or may not be included.
+///
+/// [`track_by`]: trait.Store.html#tymethod.track_by
+#[derive(Clone, Debug)]
+pub struct RedisStore {
+    /// The Redis client
+    client: RedisClient,
+}
+
+impl RedisStore {
+    /// Creates a new `RedisStore` for the given Redis URL.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the Redis URL fails to parse.
+    pub fn open(redis_url: &str) -> Result<Self, Error> {
+        Ok(RedisStore {
+            client: RedisClient::open(redis_url)?,
+        })
+    }
+}
+
+impl Store for RedisStore {
+    fn track_by(
+        &self,
+        key: String,
+        amount: usize,
+        period: Duration,
+        _now: SystemTime,
+    ) -> StoreFuture<(usize, Duration)> {
+        let exipres = period.as_secs();
+        let started = Instant::now();
+
+        let future = self
+            .client
+            .get_async_connection()
+            .from_err()
+            .and_then(move |con| {
+                // The seed of this approach is outlined Atul R in a blog post about rate
+                // limiting using NodeJS and Redis. For more details, see
+                // https://blog.atulr.com/rate-limiter/
+                let mut pipe = redis::pipe();
+                pipe.atomic()
+                    .cmd("SET")
+                    .arg(&key)
+                    .arg(0)
+                    .arg("EX")
+                    .arg(exipres)
+                    .arg("NX")
+                    .ignore()
+                    .cmd("INCRBY")
+                    .arg(&key)
+                    .arg(amount)
+                    .cmd("TTL")
+                    .arg(&key);
+
+                pipe.query_async(con)
+                    .from_err()
+                    .map(|(_, (count, ttl)): (_, (usize, u64))| (count, Duration::from_secs(ttl)))
+            })
+            .then(move |result| {
+                trace::redis_result(&result, started.elapsed());
+                result
+            });
+
+        Box::new(trace::instrument(
+            future,
+            trace::redis_span("SET NX EX, INCRBY, TTL"),
+        ))
+    }
+
+    fn track_all_within(
+        &self,
+        keys: Vec<(String, Duration, usize)>,
+        amount: usize,
+        _now: SystemTime,
+    ) -> StoreFuture<Tracked> {
+        let started = Instant::now();
+
+        let future = self
+            .client
+            .get_async_connection()
+            .from_err()
+            .and_then(move |con| {
+                let mut cmd = client::eval(TRACK_WITHIN_SCRIPT, keys.len());
+                for (key, _, _) in &keys {
+                    cmd.arg(key);
+                }
+                cmd.arg(amount);
+                for (_, period, limit) in &keys {
+                    cmd.arg(period.as_secs()).arg(*limit);
+                }
+
+                cmd.query_async(con)
+                    .from_err()
+                    .map(|(_, reached): (_, Vec<u64>)| tracked(&reached))
+            })
+            .then(move |result| {
+                trace::redis_result(&result, started.elapsed());
+                result
+            });
+
+        Box::new(trace::instrument(future, trace::redis_span("EVAL")))
+    }
+
+    fn release(&self, key: String, amount: usize, _now: SystemTime) -> StoreFuture<()> {
+        let started = Instant::now();
+
+        let future = self
+            .client
+            .get_async_connection()
+            .from_err()
+            .and_then(move |con| {
+                let watched = key.clone();
+
+                redis::cmd("WATCH")
+                    .arg(&key)
+                    .query_async(con)
+                    .and_then(move |(con, ()): (_, ())| {
+                        redis::cmd("PTTL").arg(&watched).query_async(con)
+                    })
+                    .and_then(move |(con, ttl): (_, i64)| {
+                        // A key without a TTL has either expired or was never tracked
+                        if ttl <= 0 {
+                            return Either::A(
+                                redis::cmd("UNWATCH")
+                                    .query_async(con)
+                                    .map(|(_, ()): (_, ())| ()),
+                            );
+                        }
+
+                        let mut pipe = redis::pipe();
+                        pipe.atomic().cmd("DECRBY").arg(&key).arg(amount).ignore();
+
+                        Either::B(
+                            pipe.query_async(con)
+                                .map(|(_, _aborted): (_, Option<()>)| ()),
+                        )
+                    })
+                    .from_err()
+            })
+            .then(move |result| {
+                trace::redis_result(&result, started.elapsed());
+                result
+            });
+
+        Box::new(trace::instrument(
+            future,
+            trace::redis_span("WATCH, PTTL, MULTI DECRBY EXEC"),
+        ))
+    }
+
+    fn peek(&self, key: String, _now: SystemTime) -> StoreFuture<Option<(usize, Duration)>> {
+        let started = Instant::now();
+
+        let future = self
+            .client
+            .get_async_connection()
+            .from_err()
+            .and_then(move |con| {
+                let mut pipe = redis::pipe();
+                pipe.cmd("GET").arg(&key).cmd("PTTL").arg(&key);
+
+                pipe.query_async(con).from_err().map(
+                    |(_, (count, ttl)): (_, (Option<usize>, i64))| {
+                        // A key without a TTL has either expired or was never tracked
+                        count
+                            .filter(|_| ttl > 0)
+                            .map(|count| (count, Duration::from_millis(ttl as u64)))
+                    },
+                )
+            })
+            .then(move |result| {
+                trace::redis_result(&result, started.elapsed());
+                result
+            });
+
+        Box::new(trace::instrument(future, trace::redis_span("GET, PTTL")))
+    }
+
+    fn scan(
+        &self,
+        prefix: String,
+        _now: SystemTime,
+    ) -> StoreFuture<Vec<(String, usize, Duration)>> {
+        let pattern = format!("{}*", escape_pattern(&prefix));
+        let started = Instant::now();
+
+        let future = self
+            .client
+            .get_async_connection()
+            .from_err()
+            .and_then(move |con| {
+                future::loop_fn((con, 0, Vec::new()), move |(con, cursor, mut found)| {
+                    redis::cmd("SCAN")
+                        .arg(cursor)
+                        .arg("MATCH")
+                        .arg(&pattern)
+                        .arg("COUNT")
+                        .arg(SCAN_COUNT)
+                        .query_async(con)
+                        .from_err()
+                        .and_then(|(con, (cursor, keys)): (_, (u64, Vec<String>))| {
+                            if keys.is_empty() {
+                                return Either::A(future::ok((con, cursor, Vec::new())));
+                            }
+
+                            // MGET reads keys holding other types as nil, rather than failing
+                            let mut pipe = redis::pipe();
+                            pipe.cmd("MGET").arg(&keys[..]);
+                            for key in &keys {
+                                pipe.cmd("PTTL").arg(key);
+                            }
+
+                            Either::B(pipe.query_async(con).from_err().and_then(
+                                move |(con, replies): (_, Vec<Value>)| {
+                                    let counted = counted_keys(keys, &replies)?;
+                                    Ok((con, cursor, counted))
+                                },
+                            ))
+                        })
+                        .map(move |(con, cursor, counted)| {
+                            found.extend(counted);
+                            if cursor == 0 {
+                                Loop::Break(found)
+                            } else {
+                                Loop::Continue((con, cursor, found))
+                            }
+                        })
+                })
+            })
+            .then(move |result| {
+                trace::redis_result(&result, started.elapsed());
+                result
+            });
+
+        Box::new(trace::instrument(
+            future,
+            trace::redis_span("SCAN, MGET, PTTL"),
+        ))
+    }
+}
+
+/// Escapes the characters in a key prefix which `SCAN` would treat as a glob pattern.
+fn escape_pattern(prefix: &str) -> String {
+    let mut escaped = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if let '*' | '?' | '[' | ']' | '\\' = c {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Pairs a batch of keys with the replies to an `MGET` of the keys followed by a `PTTL` of each,
+/// keeping only the keys which hold a count and expire.
+fn counted_keys(
+    keys: Vec<String>,
+    replies: &[Value],
+) -> Result<Vec<(String, usize, Duration)>, Error> {
+    let (counts, ttls) = match replies.split_first() {
+        Some((counts, ttls)) => (
+            redis::from_redis_value::<Vec<Option<String>>>(counts)?,
+            ttls,
+        ),
+        None => return Ok(Vec::new()),
+    };
+
+    keys.into_iter()
+        .zip(counts)
+        .zip(ttls)
+        .filter_map(|((key, count), ttl)| {
+            let count = count.and_then(|count| count.parse().ok())?;
+            match redis::from_redis_value::<i64>(ttl) {
+                // A key without a TTL has either expired or isn't a window
+                Ok(ttl) if ttl > 0 => Some(Ok((key, count, Duration::from_millis(ttl as u64)))),
+                Ok(_) => None,
+                Err(err) => Some(Err(err.into())),
+            }
+        })
+        .collect()
+}
+
+/// Reads the reply of the script which counts a request on several keys, made up of whether the
+/// keys were counted followed by the count and time remaining of each key.
+fn tracked(reply: &[u64]) -> Tracked {
+    let (counted, reached) = match reply.split_first() {
+        Some((counted, reached)) => (*counted == 1, reached),
+        None => (true, &[][..]),
+    };
+    let reached = reached
+        .chunks_exact(2)
+        .map(|pair| (pair[0] as usize, Duration::from_secs(pair[1])))
+        .collect();
+
+    if counted {
+        Ok(reached)
+    } else {
+        Err(reached)
+    }
+}
@@ -0,0 +1,296 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use super::{Store, StoreFuture, Tracked};
+use crate::{epoch_millis, Error};
+use futures::future;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// The number of windows tracked between each purge of expired windows
+const PURGE_EVERY: usize = 1000;
+/// The time to wait for another connection to finish writing before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Creates the windows table, unless it already exists.
+const CREATE_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS limitation_windows (
+        key TEXT PRIMARY KEY NOT NULL,
+        count INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS limitation_windows_expires_at
+        ON limitation_windows (expires_at);
+";
+
+/// Counts requests on a key's window, starting a new window if there is none or it has ended.
+const UPSERT_WINDOW: &str = "
+    INSERT INTO limitation_windows (key, count, expires_at) VALUES (?1, ?2, ?3)
+    ON CONFLICT (key) DO UPDATE SET
+        count = CASE WHEN expires_at <= ?4 THEN excluded.count ELSE count + excluded.count END,
+        expires_at = CASE WHEN expires_at <= ?4 THEN excluded.expires_at ELSE expires_at END
+";
+
+/// A `Store` which keeps its counters in a SQLite database.
+///
+/// Each window is a row in a `limitation_windows` table, which is created when the store is
+/// opened. Requests are counted with a single upsert, in a transaction which also reads the
+/// count back, so processes sharing a database file count every request exactly once. As with
+/// the [`MemoryStore`], windows end at the time passed to [`track_by`], and rows for windows
+/// which have ended are purged every so often while tracking, or on demand with
+/// [`purge_expired`].
+///
+/// Queries block the calling thread, so this store is suited to low-volume limits which must
+/// outlive a restart, such as on signup or password reset endpoints, rather than limits on every
+/// request.
+///
+/// Clones of a `SqliteStore` share the same connection.
+///
+/// [`MemoryStore`]: struct.MemoryStore.html
+/// [`purge_expired`]: #method.purge_expired
+/// [`track_by`]: trait.Store.html#tymethod.track_by
+#[derive(Clone, Debug)]
+pub struct SqliteStore {
+    /// The database connection
+    conn: Arc<Mutex<Connection>>,
+    /// The number of windows tracked, used to decide when to purge expired windows
+    tracked: Arc<AtomicUsize>,
+}
+
+impl SqliteStore {
+    /// Creates a new `SqliteStore` for the database file at the given path, which is created if
+    /// it doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the database fails to open or the table fails to be created.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::with_connection(Connection::open(path).map_err(store_error)?)
+    }
+
+    /// Creates a new `SqliteStore` for a private, in-memory database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the database fails to open or the table fails to be created.
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::with_connection(Connection::open_in_memory().map_err(store_error)?)
+    }
+
+    /// Creates a new `SqliteStore` using an existing connection, so that limits can be kept in a
+    /// database an application already uses.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the table fails to be created.
+    pub fn with_connection(conn: Connection) -> Result<Self, Error> {
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(store_error)?;
+        conn.execute_batch(CREATE_TABLE).map_err(store_error)?;
+
+        Ok(SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+            tracked: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Deletes the rows of every window which has ended by `now` and returns how many were
+    /// deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the rows fail to be deleted.
+    pub fn purge_expired(&self, now: SystemTime) -> Result<usize, Error> {
+        let conn = self.conn.lock().expect("store lock was poisoned");
+
+        conn.execute(
+            "DELETE FROM limitation_windows WHERE expires_at <= ?1",
+            params![epoch_millis(now) as i64],
+        )
+        .map_err(store_error)
+    }
+
+    /// Counts `amount` requests on each key in a single transaction and returns the count and time
+    /// remaining in each key's window.
+    ///
+    /// The transaction is rolled back if any key goes over its limit, in which case the counts
+    /// the keys would have reached are returned as the `Err` of the inner `Result`.
+    fn track_keys(
+        &self,
+        keys: Vec<(String, Duration, usize)>,
+        amount: usize,
+        now: SystemTime,
+    ) -> Result<Tracked, Error> {
+        let mut conn = self.conn.lock().expect("store lock was poisoned");
+        let now_millis = epoch_millis(now) as i64;
+
+        // An immediate transaction takes the write lock up front, so no other connection can
+        // count a request between the upsert and reading the window back
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(store_error)?;
+
+        let mut tracked = Vec::with_capacity(keys.len());
+        let mut exceeded = false;
+        for (key, period, limit) in keys {
+            tx.execute(
+                UPSERT_WINDOW,
+                params![
+                    &key,
+                    amount as i64,
+                    epoch_millis(now + period) as i64,
+                    now_millis
+                ],
+            )
+            .map_err(store_error)?;
+            let (count, expires_at): (i64, i64) = tx
+                .query_row(
+                    "SELECT count, expires_at FROM limitation_windows WHERE key = ?1",
+                    params![&key],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .map_err(store_error)?;
+
+            tracked.push((count as usize, remaining(now_millis, expires_at)));
+            exceeded |= count as usize > limit;
+        }
+        if exceeded {
+            // Dropping the transaction rolls it back, so no key is counted
+            return Ok(Err(tracked));
+        }
+
+        if self.tracked.fetch_add(1, Ordering::Relaxed) % PURGE_EVERY == PURGE_EVERY - 1 {
+            tx.execute(
+                "DELETE FROM limitation_windows WHERE expires_at <= ?1",
+                params![now_millis],
+            )
+            .map_err(store_error)?;
+        }
+
+        tx.commit().map_err(store_error)?;
+
+        Ok(Ok(tracked))
+    }
+}
+
+impl Store for SqliteStore {
+    fn track_by(
+        &self,
+        key: String,
+        amount: usize,
+        period: Duration,
+        now: SystemTime,
+    ) -> StoreFuture<(usize, Duration)> {
+        let tracked = self
+            .track_keys(vec![(key, period, usize::MAX)], amount, now)
+            .map(|tracked| tracked.unwrap_or_else(|reached| reached).remove(0));
+
+        Box::new(future::result(tracked))
+    }
+
+    fn track_all_within(
+        &self,
+        keys: Vec<(String, Duration, usize)>,
+        amount: usize,
+        now: SystemTime,
+    ) -> StoreFuture<Tracked> {
+        Box::new(future::result(self.track_keys(keys, amount, now)))
+    }
+
+    fn release(&self, key: String, amount: usize, now: SystemTime) -> StoreFuture<()> {
+        let conn = self.conn.lock().expect("store lock was poisoned");
+
+        let released = conn
+            .execute(
+                "UPDATE limitation_windows SET count = max(count - ?2, 0)
+                 WHERE key = ?1 AND expires_at > ?3",
+                params![&key, amount as i64, epoch_millis(now) as i64],
+            )
+            .map(|_| ())
+            .map_err(store_error);
+
+        Box::new(future::result(released))
+    }
+
+    fn peek(&self, key: String, now: SystemTime) -> StoreFuture<Option<(usize, Duration)>> {
+        let conn = self.conn.lock().expect("store lock was poisoned");
+        let now_millis = epoch_millis(now) as i64;
+
+        let current = conn
+            .query_row(
+                "SELECT count, expires_at FROM limitation_windows
+                 WHERE key = ?1 AND expires_at > ?2",
+                params![&key, now_millis],
+                |row| Ok((row.get::<_, i64>(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map(|current| {
+                current
+                    .map(|(count, expires_at)| (count as usize, remaining(now_millis, expires_at)))
+            })
+            .map_err(store_error);
+
+        Box::new(future::result(current))
+    }
+
+    fn scan(&self, prefix: String, now: SystemTime) -> StoreFuture<Vec<(String, usize, Duration)>> {
+        let conn = self.conn.lock().expect("store lock was poisoned");
+        let now_millis = epoch_millis(now) as i64;
+
+        let found = conn
+            .prepare(
+                "SELECT key, count, expires_at FROM limitation_windows
+                 WHERE key LIKE ?1 ESCAPE '\\' AND expires_at > ?2",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_map(
+                    params![format!("{}%", escape_like(&prefix)), now_millis],
+                    |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, i64>(1)? as usize,
+                            remaining(now_millis, row.get(2)?),
+                        ))
+                    },
+                )?
+                .collect()
+            })
+            // `LIKE` ignores ASCII case, so keys are matched exactly here
+            .map(|found: Vec<_>| {
+                found
+                    .into_iter()
+                    .filter(|(key, _, _)| key.starts_with(&prefix))
+                    .collect()
+            })
+            .map_err(store_error);
+
+        Box::new(future::result(found))
+    }
+}
+
+/// Escapes the characters in a key prefix which `LIKE` would treat as a pattern.
+fn escape_like(prefix: &str) -> String {
+    let mut escaped = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if let '%' | '_' | '\\' = c {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Returns the time remaining from `now` until a window's end, both UNIX timestamps in
+/// milliseconds.
+fn remaining(now: i64, expires_at: i64) -> Duration {
+    Duration::from_millis(expires_at.saturating_sub(now).max(0) as u64)
+}
+
+/// Wraps a database error as a store error.
+fn store_error(err: rusqlite::Error) -> Error {
+    Error::Store(Box::new(err))
+}
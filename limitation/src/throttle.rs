@@ -0,0 +1,277 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{Error, Limiter, Status};
+use futures::{try_ready, Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use std::fmt;
+use std::time::Duration;
+
+/// A pending permit from `Limiter::acquire`
+type Permit = Box<dyn Future<Item = Status, Error = Error> + Send>;
+
+/// The longest an adapter waits for a permit unless told otherwise, which is effectively forever
+const DEFAULT_MAX_WAIT: Duration = Duration::from_secs(u64::MAX / 2);
+
+/// An extension trait for `Stream`s which paces items through a [`Limiter`].
+///
+/// [`Limiter`]: struct.Limiter.html
+pub trait ThrottleExt: Stream + Sized {
+    /// Returns a stream which yields each item only once a request is permitted on `key`.
+    ///
+    /// Each item is counted on `key` with [`Limiter::acquire`], so any number of streams, in any
+    /// number of processes, which share a key and a Redis server are collectively held to the
+    /// Limiter's rate. By default the stream waits as long as it takes for each permit, which can
+    /// be bounded with [`Throttle::max_wait`].
+    ///
+    /// If a permit can't be acquired, the error is yielded and the item is kept so that polling
+    /// the stream again tries once more to send the same item. The stream's error type must
+    /// therefore be convertible from a limiter [`Error`], which can be arranged with
+    /// `Stream::map_err` beforehand if necessary.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use futures::{stream, Future, Stream};
+    /// use limitation::{Limiter, ThrottleExt};
+    ///
+    /// let limiter = Limiter::build("redis://127.0.0.1/").limit(10).finish()?;
+    /// let events = stream::iter_ok::<_, limitation::Error>(vec!["a", "b", "c"]);
+    ///
+    /// tokio::run(
+    ///     events
+    ///         .throttle(&limiter, "partner-api")
+    ///         .for_each(|event| {
+    ///             println!("sending {}", event);
+    ///             Ok(())
+    ///         })
+    ///         .map_err(|err| eprintln!("err: {}", err)),
+    /// );
+    /// # Ok::<(), limitation::Error>(())
+    /// ```
+    ///
+    /// [`Error`]: enum.Error.html
+    /// [`Limiter::acquire`]: struct.Limiter.html#method.acquire
+    /// [`Throttle::max_wait`]: struct.Throttle.html#method.max_wait
+    fn throttle<K: Into<String>>(self, limiter: &Limiter, key: K) -> Throttle<Self>
+    where
+        Self::Error: From<Error>,
+    {
+        Throttle {
+            stream: self,
+            limiter: limiter.clone(),
+            key: key.into(),
+            max_wait: DEFAULT_MAX_WAIT,
+            item: None,
+            permit: None,
+        }
+    }
+}
+
+impl<S: Stream> ThrottleExt for S {}
+
+/// An extension trait for `Sink`s which paces items through a [`Limiter`].
+///
+/// [`Limiter`]: struct.Limiter.html
+pub trait SinkThrottleExt: Sink + Sized {
+    /// Returns a sink which accepts each item only once a request is permitted on `key`.
+    ///
+    /// This is the `Sink` counterpart to [`ThrottleExt::throttle`]: an item is only passed on to
+    /// the underlying sink after a permit has been acquired for it, and the sink's error type
+    /// must be convertible from a limiter [`Error`].
+    ///
+    /// [`Error`]: enum.Error.html
+    /// [`ThrottleExt::throttle`]: trait.ThrottleExt.html#method.throttle
+    fn sink_throttle<K: Into<String>>(self, limiter: &Limiter, key: K) -> SinkThrottle<Self>
+    where
+        Self::SinkError: From<Error>,
+    {
+        SinkThrottle {
+            sink: self,
+            limiter: limiter.clone(),
+            key: key.into(),
+            max_wait: DEFAULT_MAX_WAIT,
+            permit: None,
+            permitted: false,
+        }
+    }
+}
+
+impl<S: Sink> SinkThrottleExt for S {}
+
+/// A stream which paces its items through a [`Limiter`], created by [`ThrottleExt::throttle`].
+///
+/// [`Limiter`]: struct.Limiter.html
+/// [`ThrottleExt::throttle`]: trait.ThrottleExt.html#method.throttle
+#[must_use = "streams do nothing unless polled"]
+pub struct Throttle<S: Stream> {
+    stream: S,
+    limiter: Limiter,
+    key: String,
+    max_wait: Duration,
+    /// An item waiting on a permit
+    item: Option<S::Item>,
+    permit: Option<Permit>,
+}
+
+impl<S: Stream> Throttle<S> {
+    /// Sets the longest time to wait for each item's permit before yielding an error.
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes this adapter, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S> Stream for Throttle<S>
+where
+    S: Stream,
+    S::Error: From<Error>,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.item.is_none() {
+            match try_ready!(self.stream.poll()) {
+                Some(item) => self.item = Some(item),
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+
+        match poll_permit(&mut self.permit, &self.limiter, &self.key, self.max_wait)? {
+            Async::Ready(()) => Ok(Async::Ready(self.item.take())),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+impl<S: Stream + fmt::Debug> fmt::Debug for Throttle<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Throttle")
+            .field("stream", &self.stream)
+            .field("limiter", &self.limiter)
+            .field("key", &self.key)
+            .field("max_wait", &self.max_wait)
+            .field("waiting", &self.item.is_some())
+            .finish()
+    }
+}
+
+/// A sink which paces its items through a [`Limiter`], created by
+/// [`SinkThrottleExt::sink_throttle`].
+///
+/// [`Limiter`]: struct.Limiter.html
+/// [`SinkThrottleExt::sink_throttle`]: trait.SinkThrottleExt.html#method.sink_throttle
+#[must_use = "sinks do nothing unless polled"]
+pub struct SinkThrottle<S> {
+    sink: S,
+    limiter: Limiter,
+    key: String,
+    max_wait: Duration,
+    permit: Option<Permit>,
+    /// Whether a permit was acquired for an item the underlying sink has not yet accepted
+    permitted: bool,
+}
+
+impl<S> SinkThrottle<S> {
+    /// Sets the longest time to wait for each item's permit before returning an error.
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    /// Returns a reference to the underlying sink.
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Returns a mutable reference to the underlying sink.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Consumes this adapter, returning the underlying sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S> Sink for SinkThrottle<S>
+where
+    S: Sink,
+    S::SinkError: From<Error>,
+{
+    type SinkItem = S::SinkItem;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        if !self.permitted {
+            match poll_permit(&mut self.permit, &self.limiter, &self.key, self.max_wait)? {
+                Async::Ready(()) => self.permitted = true,
+                Async::NotReady => return Ok(AsyncSink::NotReady(item)),
+            }
+        }
+
+        let result = self.sink.start_send(item)?;
+        if result.is_ready() {
+            self.permitted = false;
+        }
+        Ok(result)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.sink.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.sink.close()
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for SinkThrottle<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SinkThrottle")
+            .field("sink", &self.sink)
+            .field("limiter", &self.limiter)
+            .field("key", &self.key)
+            .field("max_wait", &self.max_wait)
+            .field("permitted", &self.permitted)
+            .finish()
+    }
+}
+
+/// Polls for a permit on a key, starting to acquire one if necessary.
+///
+/// The pending permit is cleared once it resolves, so an error leads to a fresh attempt the next
+/// time this is called.
+fn poll_permit(
+    permit: &mut Option<Permit>,
+    limiter: &Limiter,
+    key: &str,
+    max_wait: Duration,
+) -> Poll<(), Error> {
+    let result = permit
+        .get_or_insert_with(|| Box::new(limiter.acquire(key.to_string(), max_wait)))
+        .poll();
+    if let Ok(Async::NotReady) = result {
+        return Ok(Async::NotReady);
+    }
+
+    *permit = None;
+    result.map(|_| Async::Ready(()))
+}
@@ -0,0 +1,202 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Optional `tracing` instrumentation.
+//!
+//! Every function in this module has a no-op counterpart when the `tracing` feature is disabled,
+//! so call sites don't need to be conditionally compiled.
+
+use crate::{Error, Status};
+#[cfg(feature = "tracing")]
+use std::collections::hash_map::RandomState;
+#[cfg(feature = "tracing")]
+use std::fmt;
+#[cfg(feature = "tracing")]
+use std::hash::BuildHasher;
+#[cfg(feature = "tracing")]
+use std::sync::OnceLock;
+use std::time::Duration;
+
+#[cfg(feature = "tracing")]
+pub(crate) use self::enabled::*;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use self::disabled::*;
+
+/// A key as it should appear in a span or event.
+///
+/// Keys often contain sensitive values such as tokens, so unless revealing keys has been
+/// explicitly enabled, a short fingerprint of the key is displayed instead. The fingerprint is
+/// stable for the life of the process, which allows events for the same key to be correlated
+/// without recording the key itself.
+#[cfg(feature = "tracing")]
+pub(crate) struct TraceKey<'a> {
+    key: &'a str,
+    reveal: bool,
+}
+
+#[cfg(feature = "tracing")]
+impl<'a> TraceKey<'a> {
+    pub(crate) fn new(key: &'a str, reveal: bool) -> Self {
+        TraceKey { key, reveal }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl fmt::Display for TraceKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.reveal {
+            f.write_str(self.key)
+        } else {
+            write!(f, "redacted:{:016x}", fingerprint(self.key))
+        }
+    }
+}
+
+/// Computes a keyed SipHash of a key.
+///
+/// The hash key is chosen at random once per process, so fingerprints can be compared within a
+/// process but can't be reversed by hashing likely keys, such as IP addresses, and comparing.
+#[cfg(feature = "tracing")]
+fn fingerprint(key: &str) -> u64 {
+    static HASH_KEY: OnceLock<RandomState> = OnceLock::new();
+
+    HASH_KEY.get_or_init(RandomState::new).hash_one(key)
+}
+
+#[cfg(feature = "tracing")]
+mod enabled {
+    use super::*;
+    use futures::Future;
+    use tracing::{debug, debug_span, info, warn, Span};
+    use tracing_futures::Instrument;
+
+    /// Returns a span for counting a request on a key.
+    pub(crate) fn count_span(key: &str, reveal: bool, limit: usize) -> Span {
+        debug_span!("count", key = %TraceKey::new(key, reveal), limit)
+    }
+
+    /// Returns a span for a round trip to the Redis server.
+    pub(crate) fn redis_span(command: &'static str) -> Span {
+        debug_span!("redis", command)
+    }
+
+    /// Runs a Future within a span.
+    pub(crate) fn instrument<F: Future>(
+        future: F,
+        span: Span,
+    ) -> impl Future<Item = F::Item, Error = F::Error> {
+        future.instrument(span)
+    }
+
+    /// Records the result of a Redis round trip.
+    ///
+    /// The reply itself isn't recorded, as it may contain keys.
+    pub(crate) fn redis_result<T>(result: &Result<T, Error>, elapsed: Duration) {
+        match result {
+            Ok(_) => debug!(?elapsed, "redis round trip complete"),
+            Err(err) => warn!(error = %err, ?elapsed, "redis round trip failed"),
+        }
+    }
+
+    /// Returns a span for a round trip to the memcached server.
+    #[cfg(feature = "memcached")]
+    pub(crate) fn memcached_span(command: &'static str) -> Span {
+        debug_span!("memcached", command)
+    }
+
+    /// Records the result of a memcached round trip.
+    ///
+    /// The reply itself isn't recorded, as it may contain keys.
+    #[cfg(feature = "memcached")]
+    pub(crate) fn memcached_result<T>(result: &Result<T, Error>, elapsed: Duration) {
+        match result {
+            Ok(_) => debug!(?elapsed, "memcached round trip complete"),
+            Err(err) => warn!(error = %err, ?elapsed, "memcached round trip failed"),
+        }
+    }
+
+    /// Records an adjustment to an adaptive limit.
+    pub(crate) fn limit_adjusted(from: usize, to: usize) {
+        debug!(from, to, "adaptive limit adjusted");
+    }
+
+    /// Records that an event was dropped because too many were waiting to be published.
+    #[cfg(feature = "events")]
+    pub(crate) fn event_dropped() {
+        warn!("too many events waiting to be published, dropping event");
+    }
+
+    /// Records that an event couldn't be published.
+    #[cfg(feature = "events")]
+    pub(crate) fn event_failed(err: &Error) {
+        warn!(error = %err, "event could not be published");
+    }
+
+    /// Records the decision made for a request.
+    pub(crate) fn decision(result: &Result<Status, Error>) {
+        match result {
+            Ok(status) if status.would_deny() => info!(
+                count = status.count(),
+                reset = status.reset_epoch_utc(),
+                retry_after = ?status.retry_after(),
+                "rate limit exceeded in dry run, request allowed"
+            ),
+            Ok(status) => debug!(
+                count = status.count(),
+                remaining = status.remaining(),
+                reset = status.reset_epoch_utc(),
+                "request allowed"
+            ),
+            Err(Error::LimitExceeded(status)) => info!(
+                count = status.count(),
+                reset = status.reset_epoch_utc(),
+                retry_after = ?status.retry_after(),
+                "rate limit exceeded"
+            ),
+            Err(err) => warn!(error = %err, "rate limit could not be determined"),
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod disabled {
+    use super::*;
+    use futures::Future;
+
+    /// A stand-in for a span when the `tracing` feature is disabled.
+    pub(crate) struct Span;
+
+    pub(crate) fn count_span(_key: &str, _reveal: bool, _limit: usize) -> Span {
+        Span
+    }
+
+    pub(crate) fn redis_span(_command: &'static str) -> Span {
+        Span
+    }
+
+    pub(crate) fn instrument<F: Future>(future: F, _span: Span) -> F {
+        future
+    }
+
+    pub(crate) fn redis_result<T>(_result: &Result<T, Error>, _elapsed: Duration) {}
+
+    #[cfg(feature = "memcached")]
+    pub(crate) fn memcached_span(_command: &'static str) -> Span {
+        Span
+    }
+
+    #[cfg(feature = "memcached")]
+    pub(crate) fn memcached_result<T>(_result: &Result<T, Error>, _elapsed: Duration) {}
+
+    pub(crate) fn limit_adjusted(_from: usize, _to: usize) {}
+
+    #[cfg(feature = "events")]
+    pub(crate) fn event_dropped() {}
+
+    #[cfg(feature = "events")]
+    pub(crate) fn event_failed(_err: &Error) {}
+
+    pub(crate) fn decision(_result: &Result<Status, Error>) {}
+}
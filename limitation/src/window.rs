@@ -0,0 +1,107 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::epoch_secs_to_system_time;
+use chrono::{Datelike, NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use std::time::SystemTime;
+
+/// How a [`Limiter`] decides when a key's window begins and ends.
+///
+/// By default a window is the length of the configured period and begins with the first request
+/// counted on a key, so two keys will generally reset at different times. The calendar-aligned
+/// modes instead start every key's window on the same boundary, in the time zone given to the
+/// builder, so that the reported reset time lines up with a business period such as a billing
+/// day or month.
+///
+/// With the `serde` feature enabled, a `Window` is serialized as a `snake_case` string.
+///
+/// # Example
+///
+/// ```no_run
+/// use limitation::{Limiter, Tz, Window};
+///
+/// // A monthly quota which resets at midnight on the 1st, Toronto time
+/// let limiter = Limiter::build("redis://127.0.0.1/")
+///     .limit(100_000)
+///     .window(Window::Monthly)
+///     .timezone(Tz::America__Toronto)
+///     .finish()?;
+/// # Ok::<(), limitation::Error>(())
+/// ```
+///
+/// [`Limiter`]: struct.Limiter.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Window {
+    /// A window the length of the configured period, beginning with the first request on a key.
+    ///
+    /// This is the default.
+    #[default]
+    Period,
+    /// A window which begins at midnight each day.
+    Daily,
+    /// A window which begins at midnight each Monday.
+    Weekly,
+    /// A window which begins at midnight on the 1st of each month.
+    Monthly,
+}
+
+impl Window {
+    /// Returns the start and end of the calendar window containing `now`, or `None` if this is
+    /// not a calendar-aligned window.
+    pub(crate) fn bounds(self, tz: Tz, now: SystemTime) -> Option<(SystemTime, SystemTime)> {
+        let today = || {
+            chrono::DateTime::<chrono::Utc>::from(now)
+                .with_timezone(&tz)
+                .naive_local()
+                .date()
+        };
+
+        let (start, end) = match self {
+            Window::Period => return None,
+            Window::Daily => {
+                let start = today();
+                (start, start.succ())
+            }
+            Window::Weekly => {
+                let today = today();
+                let start = today
+                    - chrono::Duration::days(i64::from(today.weekday().num_days_from_monday()));
+                (start, start + chrono::Duration::days(7))
+            }
+            Window::Monthly => {
+                let today = today();
+                let start = NaiveDate::from_ymd(today.year(), today.month(), 1);
+                let end = if today.month() == 12 {
+                    NaiveDate::from_ymd(today.year() + 1, 1, 1)
+                } else {
+                    NaiveDate::from_ymd(today.year(), today.month() + 1, 1)
+                };
+                (start, end)
+            }
+        };
+
+        Some((start_of_day(tz, start), start_of_day(tz, end)))
+    }
+}
+
+/// Returns the first instant of a calendar day in a time zone.
+///
+/// Midnight does not exist on days where a daylight saving transition skips over it, in which case
+/// the first valid time after midnight is used.
+fn start_of_day(tz: Tz, date: NaiveDate) -> SystemTime {
+    let midnight = date.and_hms(0, 0, 0);
+
+    (0..=24 * 4)
+        .map(|quarters| midnight + chrono::Duration::minutes(quarters * 15))
+        .filter_map(|local| tz.from_local_datetime(&local).earliest())
+        .next()
+        .map(|datetime| epoch_secs_to_system_time(datetime.timestamp()))
+        .expect("every calendar day has a valid local time")
+}
@@ -0,0 +1,181 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{AccessList, Error, Limiter, Listing, MemoryStore};
+use std::time::Duration;
+
+#[macro_use]
+mod support;
+
+use support::{block_on, builder, clock, count_err};
+
+fn limiter(store: MemoryStore, allow: AccessList, deny: AccessList) -> Limiter {
+    builder(store, &clock())
+        .limit(1)
+        .allow(allow)
+        .deny(deny)
+        .finish()
+        .expect("limiter should build")
+}
+
+fn unlisted(store: MemoryStore) -> Limiter {
+    limiter(store, AccessList::new(), AccessList::new())
+}
+
+#[test]
+fn allowed_key_bypasses_limit() {
+    let store = MemoryStore::new();
+    let mut allow = AccessList::new();
+    allow.key("health-checker");
+    let limiter = limiter(store.clone(), allow, AccessList::new());
+
+    for _ in 0..3 {
+        let status = limiter
+            .count("health-checker")
+            .wait()
+            .expect("allowed key should be permitted");
+        assert_eq!(status.listing(), Some(Listing::Allowed));
+        assert_eq!(status.count(), 0);
+        assert_eq!(status.remaining(), 1);
+    }
+
+    // Allowed requests aren't counted
+    let status = unlisted(store)
+        .count("health-checker")
+        .wait()
+        .expect("should be under limit");
+    assert_eq!(status.count(), 1);
+    assert_eq!(status.listing(), None);
+}
+
+#[test]
+fn denied_key_is_rejected() {
+    let store = MemoryStore::new();
+    let mut deny = AccessList::new();
+    deny.key("mallory");
+    let limiter = limiter(store.clone(), AccessList::new(), deny);
+
+    let status = count_err(&limiter, "mallory");
+    assert_eq!(status.listing(), Some(Listing::Denied));
+    assert_eq!(status.remaining(), 0);
+    assert_eq!(status.retry_after(), Duration::from_secs(0));
+
+    // Denied requests aren't counted, and other keys are unaffected
+    assert_eq!(
+        unlisted(store)
+            .count("mallory")
+            .wait()
+            .expect("should be under limit")
+            .count(),
+        1
+    );
+    limiter
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+}
+
+#[test]
+fn deny_wins_over_allow() {
+    let mut allow = AccessList::new();
+    allow.key("mallory");
+    let mut deny = AccessList::new();
+    deny.key("mallory");
+    let limiter = limiter(MemoryStore::new(), allow, deny);
+
+    assert_eq!(
+        count_err(&limiter, "mallory").listing(),
+        Some(Listing::Denied)
+    );
+}
+
+#[test]
+fn networks_match_ip_keys() {
+    let mut allow = AccessList::new();
+    allow
+        .network("10.0.0.0/8")
+        .expect("network should parse")
+        .network("2001:db8::/32")
+        .expect("network should parse")
+        .network("192.168.1.7")
+        .expect("address should parse");
+    let limiter = limiter(MemoryStore::new(), allow, AccessList::new());
+
+    for key in &["10.1.2.3", "10.255.255.255", "2001:db8::1", "192.168.1.7"] {
+        let status = limiter
+            .count(*key)
+            .wait()
+            .expect("allowed key should be permitted");
+        assert_eq!(status.listing(), Some(Listing::Allowed), "key: {}", key);
+    }
+    for key in &["11.0.0.1", "2001:db9::1", "192.168.1.8", "10.0.0.0/8"] {
+        let status = limiter.count(*key).wait().expect("should be under limit");
+        assert_eq!(status.listing(), None, "key: {}", key);
+    }
+}
+
+#[test]
+fn network_with_invalid_cidr() {
+    for cidr in &["10.0.0.0/33", "::/129", "10.0.0/8", "10.0.0.0/", "alice"] {
+        match AccessList::new().network(cidr) {
+            Err(Error::Config(_)) => {}
+            other => panic!("expected config error for {}, got {:?}", cidr, other),
+        }
+    }
+}
+
+#[test]
+fn acquire_doesnt_wait_on_denied_key() {
+    let mut deny = AccessList::new();
+    deny.key("mallory");
+    let limiter = limiter(MemoryStore::new(), AccessList::new(), deny);
+
+    match block_on(limiter.acquire("mallory", Duration::from_secs(60))) {
+        Err(Error::LimitExceeded(status)) => assert_eq!(status.listing(), Some(Listing::Denied)),
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn redis_sets_match_keys_and_networks() {
+    let server = redis_server!();
+    let mut allow = AccessList::new();
+    allow
+        .redis_set(&server.url(), "allow")
+        .expect("url should parse");
+    let mut deny = AccessList::new();
+    deny.redis_set(&server.url(), "deny")
+        .expect("url should parse");
+    let limiter = limiter(MemoryStore::new(), allow, deny);
+
+    assert_eq!(
+        block_on(limiter.count("partner"))
+            .expect("should be under limit")
+            .listing(),
+        None
+    );
+
+    let mut con = server.connection();
+    let _: () = redis::cmd("SADD")
+        .arg("allow")
+        .arg("partner")
+        .arg("10.0.0.0/8")
+        .query(&mut con)
+        .expect("SADD should succeed");
+    let _: () = redis::cmd("SADD")
+        .arg("deny")
+        .arg("2001:db8::/32")
+        .query(&mut con)
+        .expect("SADD should succeed");
+
+    for key in &["partner", "10.1.2.3"] {
+        let status = block_on(limiter.count(*key)).expect("allowed key should be permitted");
+        assert_eq!(status.listing(), Some(Listing::Allowed), "key: {}", key);
+    }
+    match block_on(limiter.count("2001:db8::1")) {
+        Err(Error::LimitExceeded(status)) => assert_eq!(status.listing(), Some(Listing::Denied)),
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
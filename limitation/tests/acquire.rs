@@ -0,0 +1,82 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{Error, Limiter, MemoryStore};
+use std::time::{Duration, Instant};
+use tokio::timer::Delay;
+
+mod support;
+
+use support::block_on;
+
+fn limiter(limit: usize, period: Duration) -> Limiter {
+    Limiter::build_with_store(MemoryStore::new())
+        .limit(limit)
+        .period(period)
+        .finish()
+        .expect("limiter should build")
+}
+
+#[test]
+fn acquire_under_limit_returns_immediately() {
+    let limiter = limiter(2, Duration::from_secs(60));
+    let started = Instant::now();
+
+    let status =
+        block_on(limiter.acquire("alice", Duration::from_secs(0))).expect("acquire should succeed");
+
+    assert_eq!(status.remaining(), 1);
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn acquire_waits_for_next_period() {
+    let limiter = limiter(1, Duration::from_secs(1));
+    block_on(limiter.count("alice")).expect("should be under limit");
+    let started = Instant::now();
+
+    let status =
+        block_on(limiter.acquire("alice", Duration::from_secs(5))).expect("acquire should succeed");
+
+    assert_eq!(status.count(), 1);
+    assert!(started.elapsed() >= Duration::from_millis(500));
+}
+
+#[test]
+fn acquire_gives_up_after_max_wait() {
+    let limiter = limiter(1, Duration::from_secs(60));
+    block_on(limiter.count("alice")).expect("should be under limit");
+    let started = Instant::now();
+
+    match block_on(limiter.acquire("alice", Duration::from_secs(1))) {
+        Err(Error::LimitExceeded(status)) => assert!(status.retry_after() > Duration::from_secs(1)),
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn acquire_can_be_cancelled() {
+    let limiter = limiter(1, Duration::from_secs(60));
+    block_on(limiter.count("alice")).expect("should be under limit");
+
+    let timeout = Delay::new(Instant::now() + Duration::from_millis(50));
+    let raced = block_on(
+        limiter
+            .acquire("alice", Duration::from_secs(120))
+            .map(|_| "acquired")
+            .select2(timeout.map(|_| "timed out")),
+    );
+    match raced {
+        Ok(futures::future::Either::B(("timed out", _))) => {}
+        _ => panic!("expected the timeout to win"),
+    }
+
+    // Only the first, denied attempt was counted before the acquire was dropped
+    match block_on(limiter.count("alice")) {
+        Err(Error::LimitExceeded(status)) => assert_eq!(status.count(), 3),
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
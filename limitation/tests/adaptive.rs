@@ -0,0 +1,146 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::future;
+use limitation::{AdaptiveLimit, Error, Feedback, Limiter, ManualClock, MemoryStore};
+use std::time::Duration;
+
+#[macro_use]
+mod support;
+
+use support::{block_on, clock, RedisServer};
+
+const FAST: Feedback = Feedback::Success(Duration::from_millis(50));
+const SLOW: Feedback = Feedback::Success(Duration::from_millis(500));
+
+fn adaptive(server: &RedisServer, clock: &ManualClock) -> AdaptiveLimit {
+    AdaptiveLimit::build(&server.url())
+        .min(2)
+        .max(20)
+        .initial(10)
+        .increase(2)
+        .decrease(0.5)
+        .latency_target(Duration::from_millis(100))
+        .cooldown(Duration::from_secs(5))
+        .clock(clock.clone())
+        .finish()
+        .expect("adaptive limit should build")
+}
+
+fn report(adaptive: &AdaptiveLimit, feedback: Feedback) -> usize {
+    block_on(adaptive.report(feedback)).expect("report should succeed")
+}
+
+#[test]
+fn healthy_responses_increase_to_max() {
+    let server = redis_server!();
+    let clock = clock();
+    let adaptive = adaptive(&server, &clock);
+
+    assert_eq!(block_on(adaptive.current()).expect("should succeed"), 10);
+    assert_eq!(report(&adaptive, FAST), 12);
+    assert_eq!(report(&adaptive, FAST), 14);
+    for _ in 0..5 {
+        report(&adaptive, FAST);
+    }
+    assert_eq!(report(&adaptive, FAST), 20);
+}
+
+#[test]
+fn failures_decrease_once_per_cooldown() {
+    let server = redis_server!();
+    let clock = clock();
+    let adaptive = adaptive(&server, &clock);
+
+    assert_eq!(report(&adaptive, Feedback::Failure), 5);
+    assert_eq!(report(&adaptive, Feedback::Failure), 5);
+    assert_eq!(report(&adaptive, SLOW), 5);
+
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(report(&adaptive, SLOW), 2);
+
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(report(&adaptive, Feedback::Failure), 2);
+}
+
+#[test]
+fn limit_is_shared_across_processes() {
+    let server = redis_server!();
+    let clock = clock();
+    let first = adaptive(&server, &clock);
+    let second = adaptive(&server, &clock);
+
+    assert_eq!(block_on(second.current()).expect("should succeed"), 10);
+    report(&first, Feedback::Failure);
+
+    // The second process only sees the change once its cached limit is stale
+    assert_eq!(block_on(second.current()).expect("should succeed"), 10);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(block_on(second.current()).expect("should succeed"), 5);
+    assert_eq!(report(&second, FAST), 7);
+}
+
+#[test]
+fn concurrent_reports_are_all_applied() {
+    let server = redis_server!();
+    let clock = clock();
+    let adaptive = AdaptiveLimit::build(&server.url())
+        .max(100)
+        .initial(10)
+        .clock(clock.clone())
+        .finish()
+        .expect("adaptive limit should build");
+
+    let reports = (0..30).map(|_| adaptive.report(FAST));
+    let limits = block_on(future::join_all(reports)).expect("reports should succeed");
+    assert_eq!(limits.into_iter().max(), Some(40));
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(block_on(adaptive.current()).expect("should succeed"), 40);
+}
+
+#[test]
+fn limiter_uses_adaptive_limit() {
+    let server = redis_server!();
+    let clock = clock();
+    let adaptive = adaptive(&server, &clock);
+    let limiter = Limiter::build_with_store(MemoryStore::new())
+        .limit(1000)
+        .adaptive(adaptive.clone())
+        .clock(clock.clone())
+        .finish()
+        .expect("limiter should build");
+
+    let status = block_on(limiter.count("alice")).expect("should be under limit");
+    assert_eq!(status.limit(), 10);
+    assert_eq!(status.remaining(), 9);
+
+    report(&adaptive, Feedback::Failure);
+    let status = block_on(limiter.count("alice")).expect("should be under limit");
+    assert_eq!(status.limit(), 5);
+    assert_eq!(status.remaining(), 3);
+
+    for _ in 0..3 {
+        block_on(limiter.count("alice")).expect("should be under limit");
+    }
+    match block_on(limiter.count("alice")) {
+        Err(Error::LimitExceeded(status)) => assert_eq!(status.limit(), 5),
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn finish_with_invalid_config() {
+    let mut min_over_max = AdaptiveLimit::build("redis://127.0.0.1/");
+    min_over_max.min(10).max(5);
+    let mut no_decrease = AdaptiveLimit::build("redis://127.0.0.1/");
+    no_decrease.decrease(1.0);
+
+    for builder in &[min_over_max, no_decrease] {
+        match builder.finish() {
+            Err(Error::Config(_)) => {}
+            other => panic!("expected config error, got {:?}", other),
+        }
+    }
+}
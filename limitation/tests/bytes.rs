@@ -0,0 +1,140 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{ByteLimiter, Error, Limiter, ManualClock, MemoryStore, Status};
+use std::time::Duration;
+
+mod support;
+
+use support::{builder, clock};
+
+fn limiter(store: MemoryStore, clock: &ManualClock) -> Limiter {
+    builder(store, clock)
+        .limit(1000)
+        .finish()
+        .expect("limiter should build")
+}
+
+fn check_err(limiter: &ByteLimiter, key: &str) -> Status {
+    match limiter.check(key).wait() {
+        Err(Error::LimitExceeded(status)) => status,
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn count_by_charges_cost() {
+    let clock = clock();
+    let limiter = limiter(MemoryStore::new(), &clock);
+
+    let status = limiter
+        .count_by("alice", 600)
+        .wait()
+        .expect("should be under limit");
+    assert_eq!(status.count(), 600);
+    assert_eq!(status.remaining(), 400);
+
+    match limiter.count_by("alice", 500).wait() {
+        Err(Error::LimitExceeded(status)) => {
+            assert_eq!(status.count(), 1100);
+            assert_eq!(status.remaining(), 0);
+        }
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn check_rejects_once_budget_is_spent() {
+    let clock = clock();
+    let bytes = ByteLimiter::new(limiter(MemoryStore::new(), &clock));
+
+    let status = bytes.check("alice").wait().expect("budget should be left");
+    assert_eq!(status.count(), 0);
+    assert_eq!(status.remaining(), 1000);
+
+    // A charge may go over the budget, as the transfer has already happened
+    let status = bytes
+        .charge("alice", 700)
+        .wait()
+        .expect("charge should succeed");
+    assert_eq!(status.remaining(), 300);
+    bytes.check("alice").wait().expect("budget should be left");
+    let status = bytes
+        .charge("alice", 700)
+        .wait()
+        .expect("charge should succeed");
+    assert_eq!(status.count(), 1400);
+    assert_eq!(status.remaining(), 0);
+
+    let status = check_err(&bytes, "alice");
+    assert_eq!(status.count(), 1400);
+    assert_eq!(status.retry_after(), Duration::from_secs(60));
+    bytes.check("bob").wait().expect("budget should be left");
+
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(
+        bytes
+            .check("alice")
+            .wait()
+            .expect("budget should be left")
+            .remaining(),
+        1000
+    );
+}
+
+#[test]
+fn budgets_are_kept_apart() {
+    let clock = clock();
+    let store = MemoryStore::new();
+    let limiter = limiter(store.clone(), &clock);
+    let uploads = ByteLimiter::with_prefix(limiter.clone(), "uploads");
+    let downloads = ByteLimiter::with_prefix(limiter.clone(), "downloads");
+
+    uploads
+        .charge("alice", 1000)
+        .wait()
+        .expect("charge should succeed");
+    check_err(&uploads, "alice");
+
+    downloads
+        .check("alice")
+        .wait()
+        .expect("budget should be left");
+    assert_eq!(
+        limiter
+            .count("alice")
+            .wait()
+            .expect("should be under limit")
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn keys_cannot_spend_budgets() {
+    let clock = clock();
+    for namespace in &[None, Some("api")] {
+        let mut builder = builder(MemoryStore::new(), &clock);
+        builder.limit(1000);
+        if let Some(namespace) = namespace {
+            builder.namespace(*namespace);
+        }
+        let limiter = builder.finish().expect("limiter should build");
+        let uploads = ByteLimiter::with_prefix(limiter.clone(), "uploads");
+
+        for key in &["uploads:alice", "\0uploads:alice"] {
+            limiter
+                .count_by(*key, 1000)
+                .wait()
+                .expect("should be under limit");
+        }
+
+        let status = uploads
+            .check("alice")
+            .wait()
+            .expect("budget should be left");
+        assert_eq!(status.count(), 0);
+    }
+}
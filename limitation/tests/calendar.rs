@@ -0,0 +1,108 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{Error, Limiter, ManualClock, MemoryStore, Tz, Window};
+use std::time::Duration;
+
+mod support;
+
+use support::{builder, clock, count_ok, START};
+
+/// 2019-10-20T00:00:00Z
+const DAY_START: usize = 1_571_529_600;
+/// 2019-10-21T00:00:00Z
+const DAY_END: usize = 1_571_616_000;
+
+fn limiter(clock: &ManualClock, window: Window, timezone: Tz) -> Limiter {
+    builder(MemoryStore::new(), clock)
+        .limit(2)
+        .window(window)
+        .timezone(timezone)
+        .finish()
+        .expect("limiter should build")
+}
+
+#[test]
+fn daily_window_aligns_to_midnight() {
+    let clock = clock();
+    let limiter = limiter(&clock, Window::Daily, Tz::UTC);
+
+    let status = count_ok(&limiter, "alice");
+    assert_eq!(status.remaining(), 1);
+    assert_eq!(status.window_start_epoch_utc(), DAY_START);
+    assert_eq!(status.reset_epoch_utc(), DAY_END);
+}
+
+#[test]
+fn daily_window_resets_at_midnight() {
+    let clock = clock();
+    let limiter = limiter(&clock, Window::Daily, Tz::UTC);
+
+    count_ok(&limiter, "alice");
+    count_ok(&limiter, "alice");
+    clock.advance(Duration::from_secs(DAY_END as u64 - START - 1));
+
+    match limiter.count("alice").wait() {
+        Err(Error::LimitExceeded(status)) => {
+            assert_eq!(status.count(), 3);
+            assert_eq!(status.reset_epoch_utc(), DAY_END);
+            assert_eq!(status.retry_after(), Duration::from_secs(1));
+        }
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+
+    clock.advance(Duration::from_secs(1));
+    let status = count_ok(&limiter, "alice");
+    assert_eq!(status.count(), 1);
+    assert_eq!(status.window_start_epoch_utc(), DAY_END);
+    assert_eq!(status.reset_epoch_utc(), DAY_END + 24 * 60 * 60);
+}
+
+#[test]
+fn keys_share_window_boundaries() {
+    let clock = clock();
+    let limiter = limiter(&clock, Window::Daily, Tz::UTC);
+
+    let alice = count_ok(&limiter, "alice");
+    clock.advance(Duration::from_secs(600));
+    let bob = count_ok(&limiter, "bob");
+
+    assert_eq!(alice.reset_epoch_utc(), bob.reset_epoch_utc());
+    assert_eq!(alice.window_start_epoch_utc(), bob.window_start_epoch_utc());
+}
+
+#[test]
+fn weekly_window_aligns_to_monday() {
+    let clock = clock();
+    let limiter = limiter(&clock, Window::Weekly, Tz::UTC);
+
+    let status = count_ok(&limiter, "alice");
+    // 2019-10-14T00:00:00Z to 2019-10-21T00:00:00Z
+    assert_eq!(status.window_start_epoch_utc(), 1_571_011_200);
+    assert_eq!(status.reset_epoch_utc(), DAY_END);
+}
+
+#[test]
+fn monthly_window_uses_timezone() {
+    let clock = clock();
+    let limiter = limiter(&clock, Window::Monthly, Tz::America__Toronto);
+
+    let status = count_ok(&limiter, "alice");
+    // 2019-10-01T00:00:00-04:00 to 2019-11-01T00:00:00-04:00
+    assert_eq!(status.window_start_epoch_utc(), 1_569_902_400);
+    assert_eq!(status.reset_epoch_utc(), 1_572_580_800);
+}
+
+#[test]
+fn monthly_window_spans_daylight_saving_change() {
+    // 2019-11-15T00:00:00Z
+    let clock = ManualClock::from_epoch_secs(1_573_776_000);
+    let limiter = limiter(&clock, Window::Monthly, Tz::America__Toronto);
+
+    let status = count_ok(&limiter, "alice");
+    // 2019-11-01T00:00:00-04:00 to 2019-12-01T00:00:00-05:00
+    assert_eq!(status.window_start_epoch_utc(), 1_572_580_800);
+    assert_eq!(status.reset_epoch_utc(), 1_575_176_400);
+}
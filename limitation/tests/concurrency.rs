@@ -0,0 +1,167 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{ConcurrencyLimiter, ConcurrencyStatus, Error, ManualClock, Permit};
+use std::time::Duration;
+use tokio::runtime::current_thread::Runtime;
+
+#[macro_use]
+mod support;
+
+use support::{block_on, clock, RedisServer};
+
+fn limiter(server: &RedisServer, clock: &ManualClock) -> ConcurrencyLimiter {
+    ConcurrencyLimiter::build(&server.url())
+        .max(2)
+        .lease(Duration::from_secs(30))
+        .clock(clock.clone())
+        .finish()
+        .expect("limiter should build")
+}
+
+fn acquire_ok(limiter: &ConcurrencyLimiter, key: &str) -> Permit {
+    block_on(limiter.try_acquire(key)).expect("permit should be available")
+}
+
+fn acquire_err(limiter: &ConcurrencyLimiter, key: &str) -> ConcurrencyStatus {
+    match block_on(limiter.try_acquire(key)) {
+        Err(Error::ConcurrencyExceeded(status)) => status,
+        other => panic!("expected concurrency exceeded, got {:?}", other),
+    }
+}
+
+fn in_flight(limiter: &ConcurrencyLimiter, key: &str) -> usize {
+    block_on(limiter.status(key))
+        .expect("status should succeed")
+        .in_flight()
+}
+
+#[test]
+fn try_acquire_up_to_max() {
+    let server = redis_server!();
+    let clock = clock();
+    let limiter = limiter(&server, &clock);
+
+    let first = acquire_ok(&limiter, "alice");
+    assert_eq!(first.status().in_flight(), 1);
+    assert_eq!(first.status().available(), 1);
+    let second = acquire_ok(&limiter, "alice");
+    assert_eq!(second.status().in_flight(), 2);
+    assert_eq!(second.status().available(), 0);
+
+    let status = acquire_err(&limiter, "alice");
+    assert_eq!(status.max(), 2);
+    assert_eq!(status.in_flight(), 2);
+
+    // The rejected request doesn't hold a permit, and other keys are unaffected
+    assert_eq!(in_flight(&limiter, "alice"), 2);
+    acquire_ok(&limiter, "bob");
+}
+
+#[test]
+fn contended_acquires_take_every_free_permit() {
+    let server = redis_server!();
+    let clock = clock();
+    let limiter = ConcurrencyLimiter::build(&server.url())
+        .max(50)
+        .lease(Duration::from_secs(30))
+        .clock(clock.clone())
+        .finish()
+        .expect("limiter should build");
+
+    // Every attempt is in flight at once, so they contend for the same set
+    let attempts = (0..100).map(|_| limiter.try_acquire("alice").then(Ok::<_, ()>));
+    let results = block_on(futures::future::join_all(attempts)).expect("attempts should finish");
+
+    let mut permits = Vec::new();
+    for result in results {
+        match result {
+            Ok(permit) => permits.push(permit),
+            Err(Error::ConcurrencyExceeded(status)) => assert_eq!(status.in_flight(), 50),
+            Err(err) => panic!("expected concurrency exceeded, got {:?}", err),
+        }
+    }
+    assert_eq!(permits.len(), 50);
+    assert_eq!(in_flight(&limiter, "alice"), 50);
+}
+
+#[test]
+fn drop_outside_runtime_holds_permit_until_lease_expires() {
+    let server = redis_server!();
+    let clock = clock();
+    let limiter = limiter(&server, &clock);
+
+    let first = acquire_ok(&limiter, "alice");
+    let _second = acquire_ok(&limiter, "alice");
+    drop(first);
+
+    // Without an executor nothing is spawned, so the permit is never removed synchronously
+    assert_eq!(in_flight(&limiter, "alice"), 2);
+    clock.advance(Duration::from_secs(31));
+    assert_eq!(in_flight(&limiter, "alice"), 0);
+}
+
+#[test]
+fn drop_within_runtime_releases_permit() {
+    let server = redis_server!();
+    let clock = clock();
+    let limiter = limiter(&server, &clock);
+    let mut runtime = Runtime::new().expect("failed to start runtime");
+
+    runtime
+        .block_on(limiter.try_acquire("alice").map(drop))
+        .expect("permit should be available");
+    runtime.run().expect("spawned release should finish");
+
+    assert_eq!(in_flight(&limiter, "alice"), 0);
+}
+
+#[test]
+fn release_gives_back_permit() {
+    let server = redis_server!();
+    let clock = clock();
+    let limiter = limiter(&server, &clock);
+
+    let permit = acquire_ok(&limiter, "alice");
+    block_on(permit.release()).expect("release should succeed");
+
+    assert_eq!(in_flight(&limiter, "alice"), 0);
+}
+
+#[test]
+fn expired_permit_is_reclaimed() {
+    let server = redis_server!();
+    let clock = clock();
+    let limiter = limiter(&server, &clock);
+
+    // Simulate a holder which crashed without giving its permit back
+    std::mem::forget(acquire_ok(&limiter, "alice"));
+    clock.advance(Duration::from_secs(10));
+    let _held = acquire_ok(&limiter, "alice");
+
+    clock.advance(Duration::from_secs(19));
+    acquire_err(&limiter, "alice");
+    clock.advance(Duration::from_secs(1));
+
+    let permit = acquire_ok(&limiter, "alice");
+    assert_eq!(permit.status().in_flight(), 2);
+}
+
+#[test]
+fn renew_extends_lease() {
+    let server = redis_server!();
+    let clock = clock();
+    let limiter = limiter(&server, &clock);
+
+    let permit = acquire_ok(&limiter, "alice");
+    clock.advance(Duration::from_secs(20));
+    assert!(block_on(permit.renew()).expect("renew should succeed"));
+    clock.advance(Duration::from_secs(20));
+    assert_eq!(in_flight(&limiter, "alice"), 1);
+
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(in_flight(&limiter, "alice"), 0);
+    assert!(!block_on(permit.renew()).expect("renew should succeed"));
+}
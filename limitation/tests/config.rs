@@ -0,0 +1,186 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![cfg(feature = "config")]
+
+use futures::Future;
+use limitation::{Error, Limiter, LimiterConfig, MemoryStore, Priority};
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+#[macro_use]
+mod support;
+
+use support::{clock, TempPath};
+
+fn limiter(config: &LimiterConfig) -> Limiter {
+    Limiter::build_with_store(MemoryStore::new())
+        .config(config)
+        .clock(clock())
+        .finish()
+        .expect("limiter should build")
+}
+
+/// Counts requests on a key until one is rejected, returning how many were allowed and the
+/// rejected request's retry after.
+fn exhaust(limiter: &Limiter, key: &str) -> (usize, Duration) {
+    let mut allowed = 0;
+    loop {
+        match limiter.count(key).wait() {
+            Ok(_) => allowed += 1,
+            Err(Error::LimitExceeded(status)) => return (allowed, status.retry_after()),
+            Err(err) => panic!("unexpected error: {}", err),
+        }
+    }
+}
+
+#[test]
+fn toml_settings() {
+    let config = LimiterConfig::from_toml(
+        r#"
+        namespace = "api"
+        limit = 4
+        period = "90s"
+
+        [reserve]
+        high = 0.5
+        "#,
+    )
+    .expect("config should parse");
+    let limiter = limiter(&config);
+
+    // Half of the limit is reserved for high priority requests
+    assert_eq!(exhaust(&limiter, "alice"), (2, Duration::from_secs(90)));
+    limiter
+        .count_with_priority("alice", Priority::High)
+        .wait()
+        .expect("should be under limit");
+
+    let usage = limiter.usage().wait().expect("usage should succeed");
+    assert_eq!(usage[0].0, "alice");
+}
+
+#[test]
+fn yaml_settings() {
+    let config = LimiterConfig::from_yaml(
+        "
+        limit: 3
+        period: 120
+        window: period
+        penalties: [1h]
+        ",
+    )
+    .expect("config should parse");
+    let limiter = limiter(&config);
+
+    assert_eq!(
+        exhaust(&limiter, "alice"),
+        (3, Duration::from_secs(60 * 60))
+    );
+}
+
+#[test]
+fn invalid_settings() {
+    for toml in &[
+        "limt = 5",
+        "period = \"5w\"",
+        "period = \"999999999999999999d\"",
+        "timezone = \"Mars/Olympus\"",
+        "failure_policy = \"ajar\"",
+    ] {
+        match LimiterConfig::from_toml(toml) {
+            Err(Error::Config(_)) => {}
+            other => panic!("expected config error for {}, got {:?}", toml, other),
+        }
+    }
+}
+
+#[test]
+fn failure_settings() {
+    let config = LimiterConfig::from_toml(&format!(
+        r#"
+        url = "redis://127.0.0.1:{}/"
+        timeout = "20ms"
+        failure_policy = "open"
+        "#,
+        support::unused_port()
+    ))
+    .expect("config should parse");
+    let limiter = config
+        .builder()
+        .and_then(|builder| builder.finish())
+        .expect("limiter should build");
+
+    // Nothing listens on the port, so the request is allowed without being counted
+    let status = support::block_on(limiter.count("alice")).expect("should be allowed");
+    assert_eq!(status.count(), 0);
+}
+
+#[test]
+fn env_overrides_file() {
+    let path = TempPath::new("toml");
+    fs::write(path.path(), "limit = 10\nperiod = \"1h\"\n").expect("file should be written");
+    env::set_var("LIMITATION_TEST_ENV_LIMIT", "2");
+    env::set_var("LIMITATION_TEST_ENV_WINDOW", "Period");
+
+    let config = LimiterConfig::from_file(path.path())
+        .and_then(|config| config.merge_env("LIMITATION_TEST_ENV"))
+        .expect("config should load");
+    let limiter = limiter(&config);
+
+    assert_eq!(
+        exhaust(&limiter, "alice"),
+        (2, Duration::from_secs(60 * 60))
+    );
+}
+
+#[test]
+fn invalid_env() {
+    env::set_var("LIMITATION_TEST_BAD_PENALTIES", "1m, soon");
+
+    match LimiterConfig::from_env("LIMITATION_TEST_BAD") {
+        Err(Error::Config(msg)) => assert!(msg.contains("LIMITATION_TEST_BAD_PENALTIES")),
+        other => panic!("expected config error, got {:?}", other),
+    }
+}
+
+#[test]
+fn unsupported_file() {
+    let path = TempPath::new("ini");
+    fs::write(path.path(), "limit = 10\n").expect("file should be written");
+
+    match LimiterConfig::from_file(path.path()) {
+        Err(Error::Config(_)) => {}
+        other => panic!("expected config error, got {:?}", other),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_url() {
+    let config = LimiterConfig::from_toml("url = \"sqlite://:memory:\"\nlimit = 1\n")
+        .expect("config should parse");
+    let limiter = config
+        .builder()
+        .expect("store should open")
+        .clock(clock())
+        .finish()
+        .expect("limiter should build");
+
+    assert_eq!(exhaust(&limiter, "alice").0, 1);
+}
+
+#[cfg(not(feature = "memcached"))]
+#[test]
+fn memcached_url_without_feature() {
+    let config = LimiterConfig::from_toml("url = \"memcached://127.0.0.1:11211\"")
+        .expect("config should parse");
+
+    match config.builder() {
+        Err(Error::Config(_)) => {}
+        Err(err) => panic!("expected config error, got {:?}", err),
+        Ok(_) => panic!("expected config error"),
+    }
+}
@@ -0,0 +1,165 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{
+    AccessList, Builder, Decision, Error, Limiter, Listing, ManualClock, MemoryStore, Observer,
+    Outcome,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+mod support;
+
+use support::clock;
+
+/// An observer which records the outcome of each decision it sees.
+#[derive(Clone, Debug, Default)]
+struct Recorder {
+    outcomes: Arc<Mutex<Vec<Outcome>>>,
+}
+
+impl Observer for Recorder {
+    fn on_decision(&self, decision: &Decision<'_>) {
+        self.outcomes.lock().unwrap().push(decision.outcome());
+    }
+}
+
+fn builder(clock: &ManualClock) -> Builder<'static> {
+    let mut builder = Limiter::build_with_store(MemoryStore::new());
+    builder
+        .limit(1)
+        .period(Duration::from_secs(60))
+        .dry_run(true)
+        .clock(clock.clone());
+    builder
+}
+
+#[test]
+fn dry_run_allows_over_limit() {
+    let clock = clock();
+    let recorder = Recorder::default();
+    let limiter = builder(&clock)
+        .observer(recorder.clone())
+        .finish()
+        .expect("limiter should build");
+
+    let status = limiter.count("alice").wait().expect("should be allowed");
+    assert!(!status.would_deny());
+    assert_eq!(status.remaining(), 0);
+
+    for count in 2..4 {
+        let status = limiter.count("alice").wait().expect("should be allowed");
+        assert!(status.would_deny());
+        assert_eq!(status.count(), count);
+        assert_eq!(status.remaining(), 0);
+        assert_eq!(status.retry_after(), Duration::from_secs(60));
+    }
+
+    assert_eq!(
+        *recorder.outcomes.lock().unwrap(),
+        vec![Outcome::Allowed, Outcome::WouldDeny, Outcome::WouldDeny]
+    );
+}
+
+#[test]
+fn dry_run_disabled_by_default() {
+    let limiter = Limiter::build_with_store(MemoryStore::new())
+        .limit(1)
+        .clock(clock())
+        .finish()
+        .expect("limiter should build");
+
+    limiter
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+    limiter
+        .count("alice")
+        .wait()
+        .expect_err("should exceed limit");
+}
+
+#[test]
+fn dry_run_allows_denied_and_banned_keys() {
+    let clock = clock();
+    let mut deny = AccessList::new();
+    deny.key("mallory");
+    let limiter = builder(&clock)
+        .deny(deny)
+        .penalties(vec![Duration::from_secs(300)])
+        .finish()
+        .expect("limiter should build");
+
+    let status = limiter.count("mallory").wait().expect("should be allowed");
+    assert!(status.would_deny());
+    assert_eq!(status.listing(), Some(Listing::Denied));
+
+    limiter.count("alice").wait().expect("should be allowed");
+    let status = limiter.count("alice").wait().expect("should be allowed");
+    assert!(status.would_deny());
+    assert!(status.banned());
+
+    assert_eq!(status.retry_after(), Duration::from_secs(300));
+
+    // Neither the violation nor the ban was recorded
+    let status = limiter.count("alice").wait().expect("should be allowed");
+    assert!(status.would_deny());
+    assert_eq!(status.retry_after(), Duration::from_secs(300));
+    clock.advance(Duration::from_secs(120));
+    let status = limiter.count("alice").wait().expect("should be allowed");
+    assert!(!status.would_deny());
+    assert!(!status.banned());
+}
+
+#[test]
+fn dry_run_reports_escalating_bans_without_recording_them() {
+    let clock = clock();
+    let store = MemoryStore::new();
+    let bans = vec![Duration::from_secs(300), Duration::from_secs(900)];
+    let enforced = Limiter::build_with_store(store.clone())
+        .limit(1)
+        .period(Duration::from_secs(60))
+        .penalties(bans.clone())
+        .clock(clock.clone())
+        .finish()
+        .expect("limiter should build");
+    let dry = Limiter::build_with_store(store)
+        .limit(1)
+        .period(Duration::from_secs(60))
+        .penalties(bans)
+        .dry_run(true)
+        .clock(clock.clone())
+        .finish()
+        .expect("limiter should build");
+
+    // One real violation, after which the key's ban runs out
+    enforced
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+    enforced
+        .count("alice")
+        .wait()
+        .expect_err("should exceed limit");
+    clock.advance(Duration::from_secs(300));
+
+    dry.count("alice").wait().expect("should be allowed");
+    let status = dry.count("alice").wait().expect("should be allowed");
+    assert!(status.would_deny());
+    assert_eq!(status.retry_after(), Duration::from_secs(900));
+
+    // The dry run's violation wasn't counted towards the next ban
+    clock.advance(Duration::from_secs(60));
+    enforced
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+    match enforced.count("alice").wait() {
+        Err(Error::LimitExceeded(status)) => {
+            assert_eq!(status.retry_after(), Duration::from_secs(900));
+        }
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
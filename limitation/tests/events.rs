@@ -0,0 +1,188 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![cfg(feature = "events")]
+
+use futures::Future;
+use limitation::{
+    Destination, Error, EventKind, EventPublisher, EventSubscriber, Limiter, MemoryStore,
+};
+use serde_json::json;
+use std::time::Duration;
+
+#[macro_use]
+mod support;
+
+use support::{builder, clock, RedisServer, START};
+
+fn limiter(server: &RedisServer, destination: Destination, bans: Vec<Duration>) -> Limiter {
+    builder(MemoryStore::new(), &clock())
+        .limit(1)
+        .penalties(bans)
+        .observer(EventPublisher::new(&server.url(), destination).expect("publisher should start"))
+        .finish()
+        .expect("limiter should build")
+}
+
+fn count(limiter: &Limiter, key: &str) {
+    let _ = limiter.count(key).wait();
+}
+
+#[test]
+fn stream_receives_limit_exceeded_events() {
+    let server = redis_server!();
+    let stream = Destination::Stream("events".to_string());
+    let limiter = limiter(&server, stream.clone(), Vec::new());
+
+    count(&limiter, "alice");
+    count(&limiter, "alice");
+    count(&limiter, "bob");
+    count(&limiter, "bob");
+    count(&limiter, "bob");
+
+    let mut subscriber = EventSubscriber::open(&server.url(), stream).expect("should connect");
+    let mut events = subscriber.events().expect("should read events");
+    let event = events
+        .next()
+        .expect("should be an event")
+        .expect("should be valid");
+    assert_eq!(event.kind(), EventKind::LimitExceeded);
+    assert_eq!(event.key(), "alice");
+    assert_eq!(event.status().count(), 2);
+
+    for count in 2..4 {
+        let event = events
+            .next()
+            .expect("should be an event")
+            .expect("should be valid");
+        assert_eq!(event.key(), "bob");
+        assert_eq!(event.status().count(), count);
+    }
+}
+
+#[test]
+fn stream_payload() {
+    let server = redis_server!();
+    let limiter = limiter(
+        &server,
+        Destination::Stream("events".to_string()),
+        Vec::new(),
+    );
+
+    count(&limiter, "alice");
+    count(&limiter, "alice");
+
+    let mut subscriber =
+        EventSubscriber::open(&server.url(), Destination::Stream("events".to_string()))
+            .expect("should connect");
+    subscriber
+        .events()
+        .expect("should read events")
+        .next()
+        .expect("should be an event")
+        .expect("should be valid");
+
+    let entries: Vec<Vec<redis::Value>> = redis::cmd("XRANGE")
+        .arg("events")
+        .arg("-")
+        .arg("+")
+        .query(&mut server.connection())
+        .expect("XRANGE should succeed");
+    assert_eq!(entries.len(), 1);
+    let fields: Vec<String> =
+        redis::from_redis_value(&entries[0][1]).expect("fields should be strings");
+    assert_eq!(fields[0], "event");
+    let payload: serde_json::Value =
+        serde_json::from_str(&fields[1]).expect("payload should be json");
+    assert_eq!(
+        payload,
+        json!({
+            "kind": "limit_exceeded",
+            "key": "alice",
+            "status": {
+                "limit": 1,
+                "remaining": 0,
+                "count": 2,
+                "window_start_epoch_utc": START,
+                "reset_epoch_utc": START + 60,
+                "retry_after_ms": 60_000,
+            },
+        })
+    );
+}
+
+#[test]
+fn channel_receives_ban_events() {
+    let server = redis_server!();
+    let channel = Destination::Channel("events".to_string());
+    let limiter = limiter(&server, channel.clone(), vec![Duration::from_secs(300)]);
+
+    let mut subscriber = EventSubscriber::open(&server.url(), channel).expect("should connect");
+    let mut events = subscriber.events().expect("should subscribe");
+
+    count(&limiter, "alice");
+    count(&limiter, "alice");
+
+    let event = events
+        .next()
+        .expect("should be an event")
+        .expect("should be valid");
+    assert_eq!(event.kind(), EventKind::Banned);
+    assert_eq!(event.key(), "alice");
+    assert!(event.status().banned());
+    assert_eq!(event.status().retry_after(), Duration::from_secs(300));
+}
+
+#[test]
+fn dry_run_publishes_would_deny_events() {
+    let server = redis_server!();
+    let stream = Destination::Stream("events".to_string());
+    let limiter = builder(MemoryStore::new(), &clock())
+        .limit(1)
+        .dry_run(true)
+        .observer(
+            EventPublisher::new(&server.url(), stream.clone()).expect("publisher should start"),
+        )
+        .finish()
+        .expect("limiter should build");
+
+    count(&limiter, "alice");
+    count(&limiter, "alice");
+
+    let mut subscriber = EventSubscriber::open(&server.url(), stream).expect("should connect");
+    let event = subscriber
+        .events()
+        .expect("should read events")
+        .next()
+        .expect("should be an event")
+        .expect("should be valid");
+    assert_eq!(event.kind(), EventKind::WouldDeny);
+    assert_eq!(event.key(), "alice");
+    assert!(event.status().would_deny());
+}
+
+#[test]
+fn invalid_payload_is_an_event_error() {
+    let server = redis_server!();
+    redis::cmd("XADD")
+        .arg("events")
+        .arg("*")
+        .arg("event")
+        .arg("not an event")
+        .query::<String>(&mut server.connection())
+        .expect("XADD should succeed");
+
+    let mut subscriber =
+        EventSubscriber::open(&server.url(), Destination::Stream("events".to_string()))
+            .expect("should connect");
+    let event = subscriber
+        .events()
+        .expect("should read events")
+        .next()
+        .expect("should be an event");
+    match event {
+        Err(Error::Event(_)) => {}
+        other => panic!("expected event error, got {:?}", other),
+    }
+}
@@ -0,0 +1,167 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::future;
+use limitation::{Error, FailurePolicy, KeyPath, Limiter, Store, StoreFuture, Tracked};
+use std::time::{Duration, SystemTime};
+
+mod support;
+
+use support::{block_on, builder, clock};
+
+/// A store which never answers.
+#[derive(Clone, Debug, Default)]
+struct HangingStore;
+
+impl Store for HangingStore {
+    fn track_by(
+        &self,
+        _key: String,
+        _amount: usize,
+        _period: Duration,
+        _now: SystemTime,
+    ) -> StoreFuture<(usize, Duration)> {
+        Box::new(future::empty())
+    }
+
+    fn track_all_within(
+        &self,
+        _keys: Vec<(String, Duration, usize)>,
+        _amount: usize,
+        _now: SystemTime,
+    ) -> StoreFuture<Tracked> {
+        Box::new(future::empty())
+    }
+
+    fn release(&self, _key: String, _amount: usize, _now: SystemTime) -> StoreFuture<()> {
+        Box::new(future::empty())
+    }
+
+    fn peek(&self, _key: String, _now: SystemTime) -> StoreFuture<Option<(usize, Duration)>> {
+        Box::new(future::empty())
+    }
+}
+
+/// A store which turns away every key path without counting it, although no key is over its limit.
+#[derive(Clone, Debug, Default)]
+struct RefusingStore;
+
+impl Store for RefusingStore {
+    fn track_by(
+        &self,
+        _key: String,
+        _amount: usize,
+        period: Duration,
+        _now: SystemTime,
+    ) -> StoreFuture<(usize, Duration)> {
+        Box::new(future::ok((1, period)))
+    }
+
+    fn track_all_within(
+        &self,
+        keys: Vec<(String, Duration, usize)>,
+        amount: usize,
+        _now: SystemTime,
+    ) -> StoreFuture<Tracked> {
+        let reached = keys
+            .into_iter()
+            .map(|(_, period, _)| (amount, period))
+            .collect();
+        Box::new(future::ok(Err(reached)))
+    }
+
+    fn release(&self, _key: String, _amount: usize, _now: SystemTime) -> StoreFuture<()> {
+        Box::new(future::ok(()))
+    }
+
+    fn peek(&self, _key: String, _now: SystemTime) -> StoreFuture<Option<(usize, Duration)>> {
+        Box::new(future::ok(None))
+    }
+}
+
+fn unreachable_redis(policy: FailurePolicy) -> Limiter {
+    let url = format!("redis://127.0.0.1:{}/", support::unused_port());
+
+    Limiter::build(&url)
+        .limit(5)
+        .failure_policy(policy)
+        .finish()
+        .expect("limiter should build")
+}
+
+fn hanging(policy: FailurePolicy) -> Limiter {
+    builder(HangingStore, &clock())
+        .limit(5)
+        .timeout(Duration::from_millis(20))
+        .failure_policy(policy)
+        .finish()
+        .expect("limiter should build")
+}
+
+#[test]
+fn closed_policy_returns_failures() {
+    match block_on(unreachable_redis(FailurePolicy::Closed).count("alice")) {
+        Err(Error::Client(_)) => {}
+        other => panic!("expected client error, got {:?}", other),
+    }
+}
+
+#[test]
+fn open_policy_allows_failures() {
+    let status =
+        block_on(unreachable_redis(FailurePolicy::Open).count("alice")).expect("should be allowed");
+
+    assert_eq!(status.count(), 0);
+    assert_eq!(status.remaining(), 5);
+}
+
+#[test]
+fn timeout_is_a_failure() {
+    match block_on(hanging(FailurePolicy::Closed).count("alice")) {
+        Err(Error::Timeout(timeout)) => assert_eq!(timeout, Duration::from_millis(20)),
+        other => panic!("expected timeout, got {:?}", other),
+    }
+
+    let mut path = KeyPath::new();
+    path.level("org:acme", 10).level("user:alice", 3);
+    let status =
+        block_on(hanging(FailurePolicy::Open).count_path(&path)).expect("should be allowed");
+    assert_eq!(status.remaining(), 3);
+}
+
+#[test]
+fn refusal_within_limits_is_a_failure() {
+    let mut path = KeyPath::new();
+    path.level("org:acme", 10).level("user:alice", 3);
+    let refusing = |policy| {
+        builder(RefusingStore, &clock())
+            .failure_policy(policy)
+            .finish()
+            .expect("limiter should build")
+    };
+
+    match block_on(refusing(FailurePolicy::Closed).count_path(&path)) {
+        Err(Error::Store(_)) => {}
+        other => panic!("expected store error, got {:?}", other),
+    }
+
+    let status =
+        block_on(refusing(FailurePolicy::Open).count_path(&path)).expect("should be allowed");
+    assert_eq!(status.count(), 0);
+}
+
+#[test]
+fn limits_are_not_failures() {
+    let limiter = builder(limitation::MemoryStore::new(), &clock())
+        .limit(1)
+        .failure_policy(FailurePolicy::Open)
+        .finish()
+        .expect("limiter should build");
+
+    block_on(limiter.count("alice")).expect("should be under limit");
+    match block_on(limiter.count("alice")) {
+        Err(Error::LimitExceeded(_)) => {}
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
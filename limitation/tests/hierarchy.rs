@@ -0,0 +1,124 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{Error, KeyPath, Limiter, MemoryStore, Status};
+
+mod support;
+
+use support::{builder, clock};
+
+fn limiter(store: MemoryStore) -> Limiter {
+    builder(store, &clock())
+        .finish()
+        .expect("limiter should build")
+}
+
+fn path(user: &str, token: &str) -> KeyPath {
+    let mut path = KeyPath::new();
+    path.level("org:acme", 5)
+        .level(format!("user:{}", user), 3)
+        .level(format!("token:{}", token), 2);
+    path
+}
+
+fn count_err(limiter: &Limiter, path: &KeyPath) -> Status {
+    match limiter.count_path(path).wait() {
+        Err(Error::LimitExceeded(status)) => status,
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn reports_level_with_fewest_remaining() {
+    let limiter = limiter(MemoryStore::new());
+
+    let status = limiter
+        .count_path(&path("alice", "a1"))
+        .wait()
+        .expect("should be under limit");
+    assert_eq!(status.level(), Some("token:a1"));
+    assert_eq!(status.limit(), 2);
+    assert_eq!(status.remaining(), 1);
+
+    let status = limiter
+        .count_path(&path("alice", "a2"))
+        .wait()
+        .expect("should be under limit");
+    assert_eq!(status.level(), Some("user:alice"));
+    assert_eq!(status.remaining(), 1);
+}
+
+#[test]
+fn rejects_when_any_level_is_exhausted() {
+    let limiter = limiter(MemoryStore::new());
+
+    for _ in 0..2 {
+        limiter
+            .count_path(&path("alice", "a1"))
+            .wait()
+            .expect("should be under limit");
+    }
+    let status = count_err(&limiter, &path("alice", "a1"));
+    assert_eq!(status.level(), Some("token:a1"));
+    assert_eq!(status.remaining(), 0);
+
+    limiter
+        .count_path(&path("alice", "a2"))
+        .wait()
+        .expect("should be under limit");
+    let status = count_err(&limiter, &path("alice", "a3"));
+    assert_eq!(status.level(), Some("user:alice"));
+    assert_eq!(status.limit(), 3);
+
+    for token in &["b1", "b2"] {
+        limiter
+            .count_path(&path("bob", token))
+            .wait()
+            .expect("should be under limit");
+    }
+    assert_eq!(
+        count_err(&limiter, &path("carol", "c1")).level(),
+        Some("org:acme")
+    );
+}
+
+#[test]
+fn rejected_requests_are_not_counted() {
+    let store = MemoryStore::new();
+    let limiter = limiter(store.clone());
+
+    for _ in 0..2 {
+        limiter
+            .count_path(&path("alice", "a1"))
+            .wait()
+            .expect("should be under limit");
+    }
+    for _ in 0..10 {
+        count_err(&limiter, &path("alice", "a1"));
+    }
+
+    // No level was charged for the rejected requests
+    let status = limiter
+        .count("user:alice")
+        .wait()
+        .expect("should be under limit");
+    assert_eq!(status.count(), 3);
+    let status = limiter
+        .count("org:acme")
+        .wait()
+        .expect("should be under limit");
+    assert_eq!(status.count(), 3);
+}
+
+#[test]
+fn count_path_with_no_levels() {
+    match limiter(MemoryStore::new())
+        .count_path(&KeyPath::new())
+        .wait()
+    {
+        Err(Error::Config(_)) => {}
+        other => panic!("expected config error, got {:?}", other),
+    }
+}
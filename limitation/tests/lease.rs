@@ -0,0 +1,172 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{Error, Limiter, ManualClock, MemoryStore, Store, StoreFuture, Tracked};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+mod support;
+
+use support::{builder, clock, START};
+
+/// A `MemoryStore` which counts how often it is tracked.
+#[derive(Clone, Debug, Default)]
+struct CountingStore {
+    inner: MemoryStore,
+    calls: Arc<AtomicUsize>,
+}
+
+impl CountingStore {
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+impl Store for CountingStore {
+    fn track_by(
+        &self,
+        key: String,
+        amount: usize,
+        period: Duration,
+        now: SystemTime,
+    ) -> StoreFuture<(usize, Duration)> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.inner.track_by(key, amount, period, now)
+    }
+
+    fn track_all_within(
+        &self,
+        keys: Vec<(String, Duration, usize)>,
+        amount: usize,
+        now: SystemTime,
+    ) -> StoreFuture<Tracked> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.inner.track_all_within(keys, amount, now)
+    }
+
+    fn release(&self, key: String, amount: usize, now: SystemTime) -> StoreFuture<()> {
+        self.inner.release(key, amount, now)
+    }
+
+    fn peek(&self, key: String, now: SystemTime) -> StoreFuture<Option<(usize, Duration)>> {
+        self.inner.peek(key, now)
+    }
+}
+
+fn limiter<S: Store + 'static>(
+    store: S,
+    clock: &ManualClock,
+    limit: usize,
+    lease: usize,
+) -> Limiter {
+    builder(store, clock)
+        .limit(limit)
+        .lease(lease)
+        .finish()
+        .expect("limiter should build")
+}
+
+fn unleased(store: MemoryStore, clock: &ManualClock) -> Limiter {
+    builder(store, clock)
+        .limit(100)
+        .finish()
+        .expect("limiter should build")
+}
+
+fn count_ok(limiter: &Limiter, key: &str) -> usize {
+    limiter
+        .count(key)
+        .wait()
+        .expect("should be under limit")
+        .count()
+}
+
+#[test]
+fn lease_serves_batches_locally() {
+    let clock = clock();
+    let store = CountingStore::default();
+    let limiter = limiter(store.clone(), &clock, 100, 5);
+
+    let counts: Vec<_> = (0..10).map(|_| count_ok(&limiter, "alice")).collect();
+
+    assert_eq!(counts, (1..=10).collect::<Vec<_>>());
+    assert_eq!(store.calls(), 2);
+}
+
+#[test]
+fn lease_respects_limit() {
+    let clock = clock();
+    let store = MemoryStore::new();
+    let limiter = limiter(store.clone(), &clock, 7, 5);
+
+    for expected in 1..=7 {
+        assert_eq!(count_ok(&limiter, "alice"), expected);
+    }
+    match limiter.count("alice").wait() {
+        Err(Error::LimitExceeded(status)) => {
+            assert_eq!(status.count(), 8);
+            assert_eq!(status.remaining(), 0);
+        }
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+
+    // The part of each batch over the limit was released, leaving exactly the limit tracked
+    assert_eq!(count_ok(&unleased(store, &clock), "alice"), 8);
+}
+
+#[test]
+fn leases_share_a_store() {
+    let clock = clock();
+    let store = MemoryStore::new();
+    let first = limiter(store.clone(), &clock, 8, 5);
+    let second = limiter(store.clone(), &clock, 8, 5);
+
+    count_ok(&first, "alice");
+    count_ok(&second, "alice");
+    for _ in 0..4 {
+        count_ok(&first, "alice");
+    }
+    for _ in 0..2 {
+        count_ok(&second, "alice");
+    }
+
+    assert!(first.count("alice").wait().is_err());
+    assert!(second.count("alice").wait().is_err());
+}
+
+#[test]
+fn lease_expires_with_window() {
+    let clock = clock();
+    let store = CountingStore::default();
+    let limiter = limiter(store.clone(), &clock, 100, 5);
+
+    count_ok(&limiter, "alice");
+    clock.advance(Duration::from_secs(60));
+
+    let status = limiter
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+    assert_eq!(status.count(), 1);
+    assert_eq!(status.reset_epoch_utc(), START as usize + 120);
+    assert_eq!(store.calls(), 2);
+}
+
+#[test]
+fn release_leases_returns_unused_quota() {
+    let clock = clock();
+    let store = MemoryStore::new();
+    let limiter = limiter(store.clone(), &clock, 100, 5);
+
+    count_ok(&limiter, "alice");
+    count_ok(&limiter, "alice");
+    limiter
+        .release_leases()
+        .wait()
+        .expect("release should succeed");
+
+    assert_eq!(count_ok(&unleased(store, &clock), "alice"), 3);
+}
@@ -0,0 +1,122 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![cfg(feature = "memcached")]
+
+use limitation::{Error, Limiter, MemcachedStore, Store};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[macro_use]
+mod support;
+
+use support::{block_on, count_ok, MemcachedServer};
+
+fn store(server: &MemcachedServer) -> MemcachedStore {
+    MemcachedStore::open(&server.addr()).expect("store should open")
+}
+
+fn limiter(server: &MemcachedServer, limit: usize, period: Duration) -> Limiter {
+    Limiter::build_with_store(store(server))
+        .limit(limit)
+        .period(period)
+        .finish()
+        .expect("limiter should build")
+}
+
+#[test]
+fn count_over_limit() {
+    let server = memcached_server!();
+    let limiter = limiter(&server, 2, Duration::from_secs(60));
+
+    assert_eq!(count_ok(&limiter, "alice").remaining(), 1);
+    assert_eq!(count_ok(&limiter, "alice").remaining(), 0);
+    match block_on(limiter.count("alice")) {
+        Err(Error::LimitExceeded(status)) => {
+            assert_eq!(status.count(), 3);
+            assert!(status.retry_after() > Duration::from_secs(58));
+            assert!(status.retry_after() <= Duration::from_secs(60));
+        }
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+    assert_eq!(count_ok(&limiter, "bob").remaining(), 1);
+}
+
+#[test]
+fn window_expires() {
+    let server = memcached_server!();
+    let limiter = limiter(&server, 1, Duration::from_secs(1));
+
+    count_ok(&limiter, "alice");
+    assert!(block_on(limiter.count("alice")).is_err());
+
+    // Memcached expires keys on whole second boundaries
+    thread::sleep(Duration::from_millis(2100));
+    assert_eq!(count_ok(&limiter, "alice").count(), 1);
+}
+
+#[test]
+fn keys_are_encoded() {
+    let server = memcached_server!();
+    let limiter = limiter(&server, 5, Duration::from_secs(60));
+
+    assert_eq!(count_ok(&limiter, "Bearer abc").count(), 1);
+    assert_eq!(count_ok(&limiter, "Bearer abc").count(), 2);
+    assert_eq!(count_ok(&limiter, "Bearer%20abc").count(), 1);
+    assert_eq!(count_ok(&limiter, "Bearer\tabc").count(), 1);
+    assert_eq!(count_ok(&limiter, "\0penalty:ban:alice").count(), 1);
+
+    match block_on(limiter.count("x".repeat(250))) {
+        Err(Error::Store(_)) => {}
+        other => panic!("expected store error, got {:?}", other),
+    }
+}
+
+#[test]
+fn keys_cannot_collide_with_window_ends() {
+    let server = memcached_server!();
+    let limiter = limiter(&server, 5, Duration::from_secs(60));
+
+    count_ok(&limiter, "alice");
+    assert_eq!(count_ok(&limiter, "alice:reset").count(), 1);
+    assert_eq!(count_ok(&limiter, "%reset:alice").count(), 1);
+    assert_eq!(count_ok(&limiter, "alice").count(), 2);
+}
+
+#[test]
+fn peek_and_release() {
+    let server = memcached_server!();
+    let store = store(&server);
+    let period = Duration::from_secs(60);
+    // Windows end on a whole millisecond
+    let now = UNIX_EPOCH
+        + Duration::from_secs(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("time went backwards")
+                .as_secs(),
+        );
+
+    assert_eq!(
+        block_on(store.peek("alice".to_string(), now)).expect("peek should succeed"),
+        None
+    );
+
+    let (count, ttl) = block_on(store.track_by("alice".to_string(), 5, period, now))
+        .expect("track should succeed");
+    assert_eq!((count, ttl), (5, period));
+
+    block_on(store.release("alice".to_string(), 2, now)).expect("release should succeed");
+    let (count, ttl) = block_on(store.peek("alice".to_string(), now))
+        .expect("peek should succeed")
+        .expect("window should be current");
+    assert_eq!((count, ttl), (3, period));
+
+    // Releasing a key without a window doesn't start one
+    block_on(store.release("bob".to_string(), 1, now)).expect("release should succeed");
+    assert_eq!(
+        block_on(store.peek("bob".to_string(), now)).expect("peek should succeed"),
+        None
+    );
+}
@@ -0,0 +1,131 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{Decision, Error, Limiter, ManualClock, MemoryStore, Observer, Outcome};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+mod support;
+
+/// A recorded decision: the key, outcome, status count, and whether there was an error.
+type Recorded = (String, Outcome, Option<usize>, bool);
+
+/// An observer which records each decision it sees.
+#[derive(Clone, Debug, Default)]
+struct Recorder {
+    decisions: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl Recorder {
+    fn decisions(&self) -> Vec<Recorded> {
+        self.decisions.lock().unwrap().clone()
+    }
+}
+
+impl Observer for Recorder {
+    fn on_decision(&self, decision: &Decision<'_>) {
+        self.decisions.lock().unwrap().push((
+            decision.key().to_string(),
+            decision.outcome(),
+            decision.status().map(|status| status.count()),
+            decision.error().is_some(),
+        ));
+    }
+}
+
+#[test]
+fn observers_see_allowed_and_denied() {
+    let recorder = Recorder::default();
+    let other = Recorder::default();
+    let limiter = Limiter::build_with_store(MemoryStore::new())
+        .limit(1)
+        .period(Duration::from_secs(60))
+        .clock(ManualClock::from_epoch_secs(1_000))
+        .observer(recorder.clone())
+        .observer(other.clone())
+        .finish()
+        .expect("limiter should build");
+
+    limiter
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+    match limiter.count("alice").wait() {
+        Err(Error::LimitExceeded(_)) => {}
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+
+    let expected = vec![
+        ("alice".to_string(), Outcome::Allowed, Some(1), false),
+        ("alice".to_string(), Outcome::Denied, Some(2), false),
+    ];
+    assert_eq!(recorder.decisions(), expected);
+    assert_eq!(other.decisions(), expected);
+}
+
+#[test]
+fn observers_see_backend_errors() {
+    let recorder = Recorder::default();
+    let url = format!("redis://127.0.0.1:{}/", support::unused_port());
+    let limiter = Limiter::build(&url)
+        .observer(recorder.clone())
+        .finish()
+        .expect("limiter should build");
+
+    assert!(support::block_on(limiter.count("alice")).is_err());
+    assert_eq!(
+        recorder.decisions(),
+        vec![("alice".to_string(), Outcome::Error, None, true)]
+    );
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn metrics_observer_records_decisions() {
+    use limitation::MetricsObserver;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let limiter = Limiter::build_with_store(MemoryStore::new())
+        .limit(1)
+        .clock(ManualClock::from_epoch_secs(1_000))
+        .observer(MetricsObserver::with_prefix("test"))
+        .finish()
+        .expect("limiter should build");
+
+    metrics::with_local_recorder(&recorder, || {
+        let _ = limiter.count("alice").wait();
+        let _ = limiter.count("alice").wait();
+        let _ = limiter.count("alice").wait();
+    });
+
+    let snapshot = snapshotter.snapshot().into_vec();
+    let counter = |outcome: &str| {
+        snapshot
+            .iter()
+            .find(|(key, _, _, _)| {
+                key.kind() == MetricKind::Counter
+                    && key.key().name() == "test_decisions_total"
+                    && key
+                        .key()
+                        .labels()
+                        .any(|label| label.key() == "outcome" && label.value() == outcome)
+            })
+            .map(|(_, _, _, value)| value)
+    };
+    assert_eq!(counter("allowed"), Some(&DebugValue::Counter(1)));
+    assert_eq!(counter("denied"), Some(&DebugValue::Counter(2)));
+
+    let durations = snapshot
+        .iter()
+        .find(|(key, _, _, _)| key.key().name() == "test_decision_duration_seconds")
+        .map(|(_, _, _, value)| value);
+    match durations {
+        Some(DebugValue::Histogram(values)) => assert_eq!(values.len(), 3),
+        other => panic!("expected a histogram, got {:?}", other),
+    }
+}
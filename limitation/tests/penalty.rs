@@ -0,0 +1,158 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{Limiter, ManualClock, MemoryStore, Status};
+use std::time::Duration;
+
+mod support;
+
+use support::{builder, clock, count_err, START};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+fn limiter(clock: &ManualClock) -> Limiter {
+    builder(MemoryStore::new(), clock)
+        .limit(1)
+        .period(Duration::from_secs(10))
+        .penalties(vec![MINUTE, 10 * MINUTE, 60 * MINUTE])
+        .forgive_after(24 * 60 * MINUTE)
+        .finish()
+        .expect("limiter should build")
+}
+
+/// Exceeds the limit on a fresh window, returning the status of the violation.
+fn violate(limiter: &Limiter, clock: &ManualClock, key: &str) -> Status {
+    // Wait out the current window so the first request is permitted
+    clock.advance(Duration::from_secs(10));
+    limiter.count(key).wait().expect("should be under limit");
+    count_err(limiter, key)
+}
+
+#[test]
+fn penalties_disabled_by_default() {
+    let clock = clock();
+    let limiter = builder(MemoryStore::new(), &clock)
+        .limit(1)
+        .period(Duration::from_secs(10))
+        .finish()
+        .expect("limiter should build");
+
+    limiter
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+    let status = count_err(&limiter, "alice");
+    assert!(!status.banned());
+    assert_eq!(status.ban_expires_epoch_utc(), None);
+
+    clock.advance(Duration::from_secs(10));
+    limiter
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+}
+
+#[test]
+fn violation_bans_key() {
+    let clock = clock();
+    let limiter = limiter(&clock);
+
+    let status = violate(&limiter, &clock, "alice");
+    assert!(status.banned());
+    assert_eq!(status.remaining(), 0);
+    assert_eq!(
+        status.ban_expires_epoch_utc(),
+        Some(START as usize + 10 + 60)
+    );
+    assert_eq!(status.retry_after(), MINUTE);
+
+    // The window resets, but the ban holds and further requests don't escalate it
+    clock.advance(Duration::from_secs(30));
+    let status = count_err(&limiter, "alice");
+    assert!(status.banned());
+    assert_eq!(
+        status.ban_expires_epoch_utc(),
+        Some(START as usize + 10 + 60)
+    );
+    assert_eq!(status.retry_after(), Duration::from_secs(30));
+
+    // Other keys are unaffected
+    limiter.count("bob").wait().expect("should be under limit");
+
+    clock.advance(Duration::from_secs(30));
+    let status = limiter
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+    assert!(!status.banned());
+}
+
+#[test]
+fn repeat_violations_escalate() {
+    let clock = clock();
+    let limiter = limiter(&clock);
+
+    let bans: Vec<_> = (0..4)
+        .map(|_| {
+            let ban = violate(&limiter, &clock, "alice").retry_after();
+            clock.advance(ban);
+            ban
+        })
+        .collect();
+
+    assert_eq!(bans, vec![MINUTE, 10 * MINUTE, 60 * MINUTE, 60 * MINUTE]);
+}
+
+#[test]
+fn violations_are_forgiven() {
+    let clock = clock();
+    let limiter = limiter(&clock);
+
+    violate(&limiter, &clock, "alice");
+    clock.advance(24 * 60 * MINUTE);
+
+    assert_eq!(violate(&limiter, &clock, "alice").retry_after(), MINUTE);
+}
+
+#[test]
+fn keys_cannot_forge_penalties() {
+    let clock = clock();
+    let limiter = limiter(&clock);
+
+    for key in &[
+        "alice:penalty:ban",
+        "\0penalty:ban:alice",
+        "\0penalty:violations:alice",
+    ] {
+        limiter.count(*key).wait().expect("should be under limit");
+    }
+
+    let status = limiter
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+    assert!(!status.banned());
+}
+
+#[test]
+fn namespaced_keys_cannot_forge_penalties() {
+    let clock = clock();
+    let limiter = builder(MemoryStore::new(), &clock)
+        .limit(1)
+        .namespace("api")
+        .penalties(vec![MINUTE])
+        .finish()
+        .expect("limiter should build");
+
+    for key in &["alice:penalty:ban", "\0penalty:ban:alice"] {
+        limiter.count(*key).wait().expect("should be under limit");
+    }
+
+    let status = limiter
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+    assert!(!status.banned());
+}
@@ -0,0 +1,159 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{Error, Limiter, MemoryStore, Priority, Status};
+use std::time::Duration;
+
+mod support;
+
+use support::{builder, clock};
+
+fn limiter() -> Limiter {
+    builder(MemoryStore::new(), &clock())
+        .limit(10)
+        .reserve(Priority::High, 0.2)
+        .reserve(Priority::Normal, 0.3)
+        .finish()
+        .expect("limiter should build")
+}
+
+fn count_ok(limiter: &Limiter, priority: Priority) -> Status {
+    limiter
+        .count_with_priority("alice", priority)
+        .wait()
+        .expect("should be under limit")
+}
+
+fn count_err(limiter: &Limiter, priority: Priority) -> Status {
+    match limiter.count_with_priority("alice", priority).wait() {
+        Err(Error::LimitExceeded(status)) => status,
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn lower_classes_are_rejected_first() {
+    let limiter = limiter();
+
+    for remaining in (0..5).rev() {
+        let status = count_ok(&limiter, Priority::Low);
+        assert_eq!(status.limit(), 5);
+        assert_eq!(status.remaining(), remaining);
+    }
+    let status = count_err(&limiter, Priority::Low);
+    assert_eq!(status.limit(), 5);
+    assert_eq!(status.remaining(), 0);
+    assert_eq!(status.retry_after(), Duration::from_secs(60));
+
+    for _ in 0..3 {
+        count_ok(&limiter, Priority::Normal);
+    }
+    assert_eq!(count_err(&limiter, Priority::Normal).limit(), 8);
+
+    for _ in 0..2 {
+        assert_eq!(count_ok(&limiter, Priority::High).limit(), 10);
+    }
+    count_err(&limiter, Priority::High);
+}
+
+#[test]
+fn reserved_rejections_are_not_counted() {
+    let limiter = limiter();
+
+    for _ in 0..5 {
+        count_ok(&limiter, Priority::Low);
+    }
+    for _ in 0..20 {
+        count_err(&limiter, Priority::Low);
+    }
+
+    assert_eq!(count_ok(&limiter, Priority::High).count(), 6);
+}
+
+#[test]
+fn dry_run_keeps_reserved_rejections_counted() {
+    let limiter = builder(MemoryStore::new(), &clock())
+        .limit(10)
+        .reserve(Priority::Normal, 0.5)
+        .dry_run(true)
+        .finish()
+        .expect("limiter should build");
+
+    for _ in 0..5 {
+        count_ok(&limiter, Priority::Low);
+    }
+    let status = count_ok(&limiter, Priority::Low);
+    assert!(status.would_deny());
+    assert_eq!(status.count(), 6);
+
+    assert_eq!(count_ok(&limiter, Priority::High).count(), 7);
+}
+
+#[test]
+fn leases_respect_reserves() {
+    let store = MemoryStore::new();
+    let clock = clock();
+    let limiter = builder(store.clone(), &clock)
+        .limit(10)
+        .reserve(Priority::Normal, 0.5)
+        .lease(4)
+        .finish()
+        .expect("limiter should build");
+
+    for _ in 0..5 {
+        count_ok(&limiter, Priority::Low);
+    }
+    count_err(&limiter, Priority::Low);
+
+    // No more than the lower priority's share was kept from the leased batches
+    let unleased = builder(store, &clock)
+        .limit(10)
+        .finish()
+        .expect("limiter should build");
+    assert_eq!(
+        unleased
+            .count("alice")
+            .wait()
+            .map(|status| status.count())
+            .ok(),
+        Some(6)
+    );
+}
+
+#[test]
+fn count_is_normal_priority() {
+    let limiter = limiter();
+
+    for _ in 0..8 {
+        limiter
+            .count("alice")
+            .wait()
+            .expect("should be under limit");
+    }
+    match limiter.count("alice").wait() {
+        Err(Error::LimitExceeded(status)) => assert_eq!(status.limit(), 8),
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+    count_ok(&limiter, Priority::High);
+}
+
+#[test]
+fn finish_with_invalid_reserves() {
+    for reserves in &[
+        vec![(Priority::High, 1.5)],
+        vec![(Priority::Normal, -0.1)],
+        vec![(Priority::High, 0.6), (Priority::Normal, 0.6)],
+    ] {
+        let mut builder = Limiter::build_with_store(MemoryStore::new());
+        for (priority, fraction) in reserves {
+            builder.reserve(*priority, *fraction);
+        }
+
+        match builder.finish() {
+            Err(Error::Config(_)) => {}
+            other => panic!("expected config error for {:?}, got {:?}", reserves, other),
+        }
+    }
+}
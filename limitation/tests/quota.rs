@@ -0,0 +1,258 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Stream;
+use limitation::{
+    Error, FileSnapshotStore, ManualClock, Quota, Snapshot, SnapshotStore, Status, Window,
+};
+use std::fs;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[macro_use]
+mod support;
+
+use support::{block_on, RedisServer, TempPath};
+
+// Windows are expired with an absolute `EXPIREAT`, so tests use times in the future
+
+/// 2100-01-01T19:33:20Z
+const START: u64 = 4_102_515_200;
+/// 2100-01-01T00:00:00Z
+const DAY_START: u64 = 4_102_444_800;
+/// 2100-01-02T00:00:00Z
+const DAY_END: u64 = 4_102_531_200;
+
+fn quota(server: &RedisServer, clock: &ManualClock, snapshots: &TempPath) -> Quota {
+    Quota::build(&server.url())
+        .limit(5)
+        .window(Window::Daily)
+        .clock(clock.clone())
+        .snapshots(FileSnapshotStore::new(snapshots.path()))
+        .finish()
+        .expect("quota should build")
+}
+
+fn count_ok(quota: &Quota, key: &str) -> Status {
+    block_on(quota.count(key)).expect("should be under limit")
+}
+
+fn flushall(server: &RedisServer) {
+    let _: () = redis::cmd("FLUSHALL")
+        .query(&mut server.connection())
+        .expect("FLUSHALL should succeed");
+}
+
+#[test]
+fn count_within_calendar_window() {
+    let server = redis_server!();
+    let clock = ManualClock::from_epoch_secs(START);
+    let snapshots = TempPath::new("snapshot");
+    let quota = quota(&server, &clock, &snapshots);
+
+    let status = count_ok(&quota, "alice");
+    assert_eq!(status.limit(), 5);
+    assert_eq!(status.remaining(), 4);
+    assert_eq!(status.window_start_epoch_utc(), DAY_START as usize);
+    assert_eq!(status.reset_epoch_utc(), DAY_END as usize);
+
+    let count: usize = redis::cmd("HGET")
+        .arg(format!("limitation:quota:{}", DAY_START))
+        .arg("alice")
+        .query(&mut server.connection())
+        .expect("HGET should succeed");
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn count_over_limit() {
+    let server = redis_server!();
+    let clock = ManualClock::from_epoch_secs(START);
+    let snapshots = TempPath::new("snapshot");
+    let quota = quota(&server, &clock, &snapshots);
+
+    for _ in 0..5 {
+        count_ok(&quota, "alice");
+    }
+
+    match block_on(quota.count("alice")) {
+        Err(Error::LimitExceeded(status)) => {
+            assert_eq!(status.count(), 6);
+            assert_eq!(status.retry_after(), Duration::from_secs(DAY_END - START));
+        }
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn snapshot_saves_current_window() {
+    let server = redis_server!();
+    let clock = ManualClock::from_epoch_secs(START);
+    let snapshots = TempPath::new("snapshot");
+    let quota = quota(&server, &clock, &snapshots);
+
+    count_ok(&quota, "alice");
+    count_ok(&quota, "alice");
+    count_ok(&quota, "bob");
+
+    let snapshot = block_on(quota.snapshot()).expect("snapshot should succeed");
+    assert_eq!(
+        snapshot.window_start(),
+        UNIX_EPOCH + Duration::from_secs(DAY_START)
+    );
+    assert_eq!(
+        snapshot.window_end(),
+        UNIX_EPOCH + Duration::from_secs(DAY_END)
+    );
+    assert_eq!(snapshot.len(), 2);
+    assert_eq!(snapshot.count("alice"), 2);
+    assert_eq!(snapshot.count("bob"), 1);
+
+    let saved = FileSnapshotStore::new(snapshots.path())
+        .load()
+        .expect("snapshot should load");
+    assert_eq!(saved, Some(snapshot));
+}
+
+#[test]
+fn restore_after_data_loss() {
+    let server = redis_server!();
+    let clock = ManualClock::from_epoch_secs(START);
+    let snapshots = TempPath::new("snapshot");
+    let quota = quota(&server, &clock, &snapshots);
+
+    count_ok(&quota, "alice");
+    count_ok(&quota, "alice");
+    count_ok(&quota, "bob");
+    block_on(quota.snapshot()).expect("snapshot should succeed");
+
+    flushall(&server);
+    assert_eq!(count_ok(&quota, "alice").count(), 1);
+
+    assert_eq!(
+        block_on(quota.restore()).expect("restore should succeed"),
+        2
+    );
+    assert_eq!(count_ok(&quota, "alice").count(), 4);
+    assert_eq!(count_ok(&quota, "bob").count(), 2);
+}
+
+#[test]
+fn restore_without_data_loss_is_noop() {
+    let server = redis_server!();
+    let clock = ManualClock::from_epoch_secs(START);
+    let snapshots = TempPath::new("snapshot");
+    let quota = quota(&server, &clock, &snapshots);
+
+    count_ok(&quota, "alice");
+    block_on(quota.snapshot()).expect("snapshot should succeed");
+    count_ok(&quota, "alice");
+
+    assert_eq!(
+        block_on(quota.restore()).expect("restore should succeed"),
+        0
+    );
+    assert_eq!(count_ok(&quota, "alice").count(), 3);
+}
+
+#[test]
+fn keys_cannot_overwrite_the_generation() {
+    let server = redis_server!();
+    let clock = ManualClock::from_epoch_secs(START);
+    let snapshots = TempPath::new("snapshot");
+    let quota = quota(&server, &clock, &snapshots);
+
+    count_ok(&quota, "alice");
+    count_ok(&quota, "\0generation");
+    let snapshot = block_on(quota.snapshot()).expect("snapshot should succeed");
+    assert_eq!(snapshot.len(), 2);
+    assert_eq!(snapshot.count("\0generation"), 1);
+
+    // The window wasn't recreated, so there is nothing to restore
+    assert_eq!(
+        block_on(quota.restore()).expect("restore should succeed"),
+        0
+    );
+    assert_eq!(count_ok(&quota, "alice").count(), 2);
+}
+
+#[test]
+fn restore_ignores_snapshot_of_earlier_window() {
+    let server = redis_server!();
+    let clock = ManualClock::from_epoch_secs(START);
+    let snapshots = TempPath::new("snapshot");
+    let quota = quota(&server, &clock, &snapshots);
+
+    count_ok(&quota, "alice");
+    block_on(quota.snapshot()).expect("snapshot should succeed");
+    flushall(&server);
+    clock.advance(Duration::from_secs(DAY_END - START));
+
+    assert_eq!(
+        block_on(quota.restore()).expect("restore should succeed"),
+        0
+    );
+    assert_eq!(count_ok(&quota, "alice").count(), 1);
+}
+
+#[test]
+fn restore_without_snapshot_is_noop() {
+    let server = redis_server!();
+    let clock = ManualClock::from_epoch_secs(START);
+    let snapshots = TempPath::new("snapshot");
+    let quota = quota(&server, &clock, &snapshots);
+
+    assert_eq!(
+        block_on(quota.restore()).expect("restore should succeed"),
+        0
+    );
+}
+
+#[test]
+fn snapshots_every_interval() {
+    let server = redis_server!();
+    let clock = ManualClock::from_epoch_secs(START);
+    let snapshots = TempPath::new("snapshot");
+    let quota = quota(&server, &clock, &snapshots);
+
+    count_ok(&quota, "alice");
+
+    let taken = block_on(
+        quota
+            .snapshots_every(Duration::from_millis(10))
+            .take(2)
+            .collect(),
+    )
+    .expect("snapshots should succeed");
+    assert_eq!(taken.len(), 2);
+    assert!(taken.iter().all(|snapshot| snapshot.count("alice") == 1));
+}
+
+#[test]
+fn file_snapshot_store_round_trip() {
+    let path = TempPath::new("snapshot");
+    let store = FileSnapshotStore::new(path.path());
+    let mut snapshot = Snapshot::new(
+        UNIX_EPOCH + Duration::from_secs(DAY_START),
+        UNIX_EPOCH + Duration::from_secs(DAY_END),
+        42,
+    );
+    snapshot.insert("alice", 3);
+    snapshot.insert("token with spaces", 2);
+    snapshot.insert("line\nbreak\\slash", 1);
+
+    assert_eq!(store.load().expect("load should succeed"), None);
+    store.save(&snapshot).expect("save should succeed");
+    assert_eq!(store.load().expect("load should succeed"), Some(snapshot));
+}
+
+#[test]
+fn file_snapshot_store_rejects_invalid_file() {
+    let path = TempPath::new("snapshot");
+    fs::write(path.path(), "not a snapshot\n").expect("write should succeed");
+
+    match FileSnapshotStore::new(path.path()).load() {
+        Err(Error::Snapshot(_)) => {}
+        other => panic!("expected snapshot error, got {:?}", other),
+    }
+}
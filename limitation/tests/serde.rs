@@ -0,0 +1,108 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![cfg(feature = "serde")]
+
+use futures::Future;
+use limitation::{ErrorKind, ErrorReport, Limiter, ManualClock, MemoryStore, Status};
+use serde_json::json;
+use std::time::Duration;
+
+mod support;
+
+use support::{builder, clock, START};
+
+fn limiter(clock: &ManualClock) -> Limiter {
+    builder(MemoryStore::new(), clock)
+        .limit(1)
+        .finish()
+        .expect("limiter should build")
+}
+
+#[test]
+fn status_round_trip() {
+    let clock = clock();
+    let status = limiter(&clock)
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+
+    let value = serde_json::to_value(&status).expect("status should serialize");
+    assert_eq!(
+        value,
+        json!({
+            "limit": 1,
+            "remaining": 0,
+            "count": 1,
+            "window_start_epoch_utc": START,
+            "reset_epoch_utc": START + 60,
+            "retry_after_ms": 60_000,
+        })
+    );
+
+    let parsed: Status = serde_json::from_value(value).expect("status should deserialize");
+    assert_eq!(parsed, status);
+}
+
+#[test]
+fn limit_exceeded_report() {
+    let clock = clock();
+    let limiter = limiter(&clock);
+    limiter
+        .count("alice")
+        .wait()
+        .expect("should be under limit");
+    clock.advance(Duration::from_millis(1_500));
+
+    let err = limiter
+        .count("alice")
+        .wait()
+        .expect_err("should exceed limit");
+    let report = ErrorReport::from(&err);
+    assert_eq!(report.kind(), ErrorKind::LimitExceeded);
+
+    let value = serde_json::to_value(&report).expect("report should serialize");
+    assert_eq!(value["kind"], "limit_exceeded");
+    assert_eq!(value["status"]["count"], 2);
+    assert_eq!(value["status"]["retry_after_ms"], 58_500);
+
+    let parsed: ErrorReport = serde_json::from_value(value).expect("report should deserialize");
+    assert_eq!(parsed, report);
+}
+
+#[test]
+fn client_error_report() {
+    let err = Limiter::build("http://127.0.0.1/")
+        .finish()
+        .expect_err("url should be invalid");
+    let report = ErrorReport::from(&err);
+    assert_eq!(report.kind(), ErrorKind::Client);
+
+    let value = serde_json::to_value(&report).expect("report should serialize");
+    assert_eq!(value["kind"], "client");
+    assert!(value.get("status").is_none());
+}
+
+#[test]
+fn concurrency_exceeded_report() {
+    let value = serde_json::json!({
+        "kind": "concurrency_exceeded",
+        "message": "concurrency limit exceeded",
+        "concurrency": { "max": 2, "in_flight": 2 }
+    });
+
+    let report: ErrorReport = serde_json::from_value(value.clone()).expect("should deserialize");
+    assert_eq!(report.kind(), ErrorKind::ConcurrencyExceeded);
+    assert!(report.status().is_none());
+    let concurrency = report
+        .concurrency()
+        .expect("should have a concurrency status");
+    assert_eq!(concurrency.in_flight(), 2);
+    assert_eq!(concurrency.available(), 0);
+
+    assert_eq!(
+        serde_json::to_value(&report).expect("should serialize"),
+        value
+    );
+}
@@ -0,0 +1,118 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![cfg(feature = "sqlite")]
+
+use futures::Future;
+use limitation::{Clock, Error, Limiter, ManualClock, SqliteStore, Store};
+use std::time::Duration;
+
+#[macro_use]
+mod support;
+
+use support::{builder, clock, TempPath};
+
+fn limiter(store: &SqliteStore, clock: &ManualClock) -> Limiter {
+    namespaced(store, clock, "sign_up")
+}
+
+fn namespaced(store: &SqliteStore, clock: &ManualClock, namespace: &str) -> Limiter {
+    builder(store.clone(), clock)
+        .limit(2)
+        .namespace(namespace)
+        .finish()
+        .expect("limiter should build")
+}
+
+fn count(limiter: &Limiter, key: &str) -> Result<usize, Error> {
+    limiter.count(key).wait().map(|status| status.count())
+}
+
+#[test]
+fn window_resets_after_period() {
+    let store = SqliteStore::open_in_memory().expect("store should open");
+    let clock = clock();
+    let limiter = limiter(&store, &clock);
+
+    assert_eq!(count(&limiter, "alice").ok(), Some(1));
+    assert_eq!(count(&limiter, "alice").ok(), Some(2));
+    match limiter.count("alice").wait() {
+        Err(Error::LimitExceeded(status)) => {
+            assert_eq!(status.retry_after(), Duration::from_secs(60))
+        }
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+    assert_eq!(count(&limiter, "bob").ok(), Some(1));
+
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(count(&limiter, "alice").ok(), Some(1));
+}
+
+#[test]
+fn database_file_is_shared_and_durable() {
+    let path = TempPath::new("db");
+    let clock = clock();
+
+    let first = SqliteStore::open(path.path()).expect("store should open");
+    let second = SqliteStore::open(path.path()).expect("store should open");
+    assert_eq!(count(&limiter(&first, &clock), "alice").ok(), Some(1));
+    assert_eq!(count(&limiter(&second, &clock), "alice").ok(), Some(2));
+    drop(first);
+    drop(second);
+
+    let reopened = SqliteStore::open(path.path()).expect("store should reopen");
+    assert!(count(&limiter(&reopened, &clock), "alice").is_err());
+}
+
+#[test]
+fn peek_release_and_purge() {
+    let store = SqliteStore::open_in_memory().expect("store should open");
+    let clock = clock();
+    let now = clock.now();
+    let period = Duration::from_secs(60);
+
+    store
+        .track_by("alice".to_string(), 5, period, now)
+        .wait()
+        .expect("track should succeed");
+    store
+        .release("alice".to_string(), 2, now)
+        .wait()
+        .expect("release should succeed");
+    assert_eq!(
+        store.peek("alice".to_string(), now).wait().ok(),
+        Some(Some((3, period)))
+    );
+
+    // Releasing a key without a window doesn't start one
+    store
+        .release("bob".to_string(), 1, now)
+        .wait()
+        .expect("release should succeed");
+    assert_eq!(store.peek("bob".to_string(), now).wait().ok(), Some(None));
+
+    let later = now + period;
+    assert_eq!(
+        store.peek("alice".to_string(), later).wait().ok(),
+        Some(None)
+    );
+    assert_eq!(store.purge_expired(now).ok(), Some(0));
+    assert_eq!(store.purge_expired(later).ok(), Some(1));
+}
+
+#[test]
+fn usage_matches_prefix_exactly() {
+    let store = SqliteStore::open_in_memory().expect("store should open");
+    let clock = clock();
+    let limiter = limiter(&store, &clock);
+
+    count(&limiter, "alice").expect("should be under limit");
+    // Neither a pattern character nor a change of case matches another namespace
+    count(&namespaced(&store, &clock, "signxup"), "bob").expect("should be under limit");
+    count(&namespaced(&store, &clock, "SIGN_UP"), "carol").expect("should be under limit");
+
+    let usage = limiter.usage().wait().expect("usage should succeed");
+    let keys: Vec<_> = usage.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, vec!["alice"]);
+}
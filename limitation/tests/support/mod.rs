@@ -0,0 +1,367 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Test support for running throwaway `redis-server` and `memcached` processes, along with
+//! fixtures shared by the tests of a `Limiter`.
+//!
+//! The approach is inspired by the test infrastructure in the [redis] crate. Each server is
+//! started on a random local TCP port (or a Unix socket) with persistence disabled and is killed
+//! when dropped.
+//!
+//! If no `redis-server` program can be found on the `PATH`, tests using the [`redis_server!`]
+//! macro fail, and likewise for `memcached` and the [`memcached_server!`] macro, so that a
+//! missing server can't pass for a passing suite. Setting the `LIMITATION_SKIP_BACKEND_TESTS`
+//! environment variable to `1` skips these tests instead, with a message on the standard error
+//! stream.
+//!
+//! [redis]: https://github.com/mitsuhiko/redis-rs/blob/master/tests/support/mod.rs
+
+#![allow(dead_code)]
+
+use futures::Future;
+use limitation::{Builder, Error, Limiter, ManualClock, Status, Store};
+use std::env;
+use std::fs;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The time tests start at with a `ManualClock`, 2019-10-20T19:33:20Z (a Sunday)
+pub const START: u64 = 1_571_600_000;
+
+/// The program name of the Redis server
+const REDIS_SERVER: &str = "redis-server";
+/// The program name of the memcached server
+const MEMCACHED: &str = "memcached";
+/// The maximum time to wait for a spawned server to accept connections
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+/// The environment variable which skips tests whose server isn't installed, rather than failing
+const SKIP_BACKEND_TESTS: &str = "LIMITATION_SKIP_BACKEND_TESTS";
+
+/// Starts a `RedisServer` on a TCP port, or returns early from the test if `redis-server` is not
+/// installed and backend tests may be skipped.
+#[allow(unused_macros)]
+macro_rules! redis_server {
+    () => {
+        redis_server!(support::RedisServer::new())
+    };
+    ($server:expr) => {
+        match $server {
+            Some(server) => server,
+            None => {
+                support::skip_missing("redis-server");
+                return;
+            }
+        }
+    };
+}
+
+/// Starts a `MemcachedServer` on a TCP port, or returns early from the test if `memcached` is not
+/// installed and backend tests may be skipped.
+#[allow(unused_macros)]
+macro_rules! memcached_server {
+    () => {
+        match support::MemcachedServer::new() {
+            Some(server) => server,
+            None => {
+                support::skip_missing("memcached");
+                return;
+            }
+        }
+    };
+}
+
+/// Reports a test skipped because a server program isn't installed.
+///
+/// # Panics
+///
+/// Panics unless skipping is enabled with the `LIMITATION_SKIP_BACKEND_TESTS` environment
+/// variable.
+pub fn skip_missing(program: &str) {
+    if env::var(SKIP_BACKEND_TESTS).ok().as_deref() != Some("1") {
+        panic!(
+            "{} not found on PATH; install it, or set {}=1 to skip tests which need it",
+            program, SKIP_BACKEND_TESTS
+        );
+    }
+
+    eprintln!("{} not found on PATH, skipping test", program);
+}
+
+/// The address a `RedisServer` listens on.
+#[derive(Clone, Debug)]
+pub enum ServerAddr {
+    Tcp(String, u16),
+    Unix(PathBuf),
+}
+
+/// A `redis-server` child process which is killed on drop.
+pub struct RedisServer {
+    process: Child,
+    addr: ServerAddr,
+}
+
+impl RedisServer {
+    /// Starts a new server listening on a random local TCP port.
+    ///
+    /// Returns `None` if the `redis-server` program could not be found.
+    pub fn new() -> Option<Self> {
+        let port = unused_port();
+
+        Self::with_addr(ServerAddr::Tcp("127.0.0.1".to_string(), port))
+    }
+
+    /// Starts a new server listening on a Unix socket in a temporary directory.
+    ///
+    /// Returns `None` if the `redis-server` program could not be found.
+    #[cfg(unix)]
+    pub fn new_unix() -> Option<Self> {
+        let path = env::temp_dir().join(format!(
+            "limitation-test-{}-{}.sock",
+            process::id(),
+            unused_port()
+        ));
+
+        Self::with_addr(ServerAddr::Unix(path))
+    }
+
+    /// Starts a new server listening on a random local TCP port, passing extra configuration
+    /// arguments such as `--requirepass`.
+    ///
+    /// Returns `None` if the `redis-server` program could not be found.
+    pub fn with_args(args: &[&str]) -> Option<Self> {
+        let port = unused_port();
+
+        Self::spawn(ServerAddr::Tcp("127.0.0.1".to_string(), port), args)
+    }
+
+    /// Starts a new server listening on the given address.
+    ///
+    /// Returns `None` if the `redis-server` program could not be found.
+    pub fn with_addr(addr: ServerAddr) -> Option<Self> {
+        Self::spawn(addr, &[])
+    }
+
+    fn spawn(addr: ServerAddr, args: &[&str]) -> Option<Self> {
+        let mut cmd = Command::new(REDIS_SERVER);
+        cmd.stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .arg("--save")
+            .arg("")
+            .arg("--appendonly")
+            .arg("no");
+        match addr {
+            ServerAddr::Tcp(ref host, port) => {
+                cmd.arg("--bind")
+                    .arg(host)
+                    .arg("--port")
+                    .arg(port.to_string());
+            }
+            ServerAddr::Unix(ref path) => {
+                cmd.arg("--port").arg("0").arg("--unixsocket").arg(path);
+            }
+        }
+        cmd.args(args);
+
+        let process = match cmd.spawn() {
+            Ok(process) => process,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => panic!("failed to spawn {}: {}", REDIS_SERVER, err),
+        };
+        let server = RedisServer { process, addr };
+        server.wait_for_ready();
+
+        Some(server)
+    }
+
+    /// Returns the address the server listens on.
+    pub fn addr(&self) -> &ServerAddr {
+        &self.addr
+    }
+
+    /// Returns a Redis URL which connects to the server.
+    pub fn url(&self) -> String {
+        match self.addr {
+            ServerAddr::Tcp(ref host, port) => format!("redis://{}:{}/", host, port),
+            ServerAddr::Unix(ref path) => format!("redis+unix:{}", path.display()),
+        }
+    }
+
+    /// Returns a Redis URL which connects to the server over TCP with the given username and
+    /// password.
+    pub fn url_with_credentials(&self, username: &str, password: &str) -> String {
+        match self.addr {
+            ServerAddr::Tcp(ref host, port) => {
+                format!("redis://{}:{}@{}:{}/", username, password, host, port)
+            }
+            ServerAddr::Unix(_) => panic!("credentials are only given in TCP URLs"),
+        }
+    }
+
+    /// Returns a synchronous connection to the server, useful for inspecting or seeding keys.
+    pub fn connection(&self) -> redis::Connection {
+        redis::Client::open(self.url().as_str())
+            .expect("failed to create client")
+            .get_connection()
+            .expect("failed to connect to redis-server")
+    }
+
+    /// Blocks until the server accepts connections.
+    fn wait_for_ready(&self) {
+        let started = Instant::now();
+
+        while !self.accepts_connections() {
+            if started.elapsed() > STARTUP_TIMEOUT {
+                panic!("{} did not start on {:?}", REDIS_SERVER, self.addr);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn accepts_connections(&self) -> bool {
+        match self.addr {
+            ServerAddr::Tcp(ref host, port) => TcpStream::connect((host.as_str(), port)).is_ok(),
+            #[cfg(unix)]
+            ServerAddr::Unix(ref path) => std::os::unix::net::UnixStream::connect(path).is_ok(),
+            #[cfg(not(unix))]
+            ServerAddr::Unix(_) => false,
+        }
+    }
+}
+
+impl Drop for RedisServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        if let ServerAddr::Unix(ref path) = self.addr {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// A `memcached` child process which is killed on drop.
+pub struct MemcachedServer {
+    process: Child,
+    port: u16,
+}
+
+impl MemcachedServer {
+    /// Starts a new server listening on a random local TCP port.
+    ///
+    /// Returns `None` if the `memcached` program could not be found.
+    pub fn new() -> Option<Self> {
+        let port = unused_port();
+
+        let process = match Command::new(MEMCACHED)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .arg("--listen")
+            .arg("127.0.0.1")
+            .arg("--port")
+            .arg(port.to_string())
+            .arg("--udp-port")
+            .arg("0")
+            .spawn()
+        {
+            Ok(process) => process,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => panic!("failed to spawn {}: {}", MEMCACHED, err),
+        };
+        let server = MemcachedServer { process, port };
+
+        let started = Instant::now();
+        while TcpStream::connect(server.addr()).is_err() {
+            if started.elapsed() > STARTUP_TIMEOUT {
+                panic!("{} did not start on port {}", MEMCACHED, port);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Some(server)
+    }
+
+    /// Returns the address the server listens on.
+    pub fn addr(&self) -> String {
+        format!("127.0.0.1:{}", self.port)
+    }
+}
+
+impl Drop for MemcachedServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// A path in the temporary directory which is removed on drop.
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    /// Returns a new, unused path with the given file extension.
+    pub fn new(extension: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        TempPath(env::temp_dir().join(format!(
+            "limitation-test-{}-{}.{}",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst),
+            extension
+        )))
+    }
+
+    /// Returns the path.
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Returns a local TCP port which was free at the time of the call.
+pub fn unused_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .expect("failed to find an unused port")
+}
+
+/// Returns a `ManualClock` set to `START`.
+pub fn clock() -> ManualClock {
+    ManualClock::from_epoch_secs(START)
+}
+
+/// Returns a builder for a `Limiter` on a store, with a period of 60 seconds and the given clock.
+pub fn builder<S: Store + 'static>(store: S, clock: &ManualClock) -> Builder<'static> {
+    let mut builder = Limiter::build_with_store(store);
+    builder.period(Duration::from_secs(60)).clock(clock.clone());
+    builder
+}
+
+/// Counts a request on a key which must be under the limit.
+pub fn count_ok(limiter: &Limiter, key: &str) -> Status {
+    block_on(limiter.count(key)).expect("should be under limit")
+}
+
+/// Counts a request on a key which must exceed the limit, and returns its status.
+pub fn count_err(limiter: &Limiter, key: &str) -> Status {
+    match block_on(limiter.count(key)) {
+        Err(Error::LimitExceeded(status)) => status,
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
+
+/// Drives a Future to completion on a single-threaded Tokio runtime.
+pub fn block_on<F: Future>(future: F) -> Result<F::Item, F::Error> {
+    tokio::runtime::current_thread::Runtime::new()
+        .expect("failed to start runtime")
+        .block_on(future)
+}
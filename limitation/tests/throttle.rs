@@ -0,0 +1,109 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::{stream, Sink, Stream};
+use limitation::{Error, Limiter, MemoryStore, SinkThrottleExt, ThrottleExt};
+use std::time::{Duration, Instant};
+
+mod support;
+
+use support::block_on;
+
+fn limiter(limit: usize, period: Duration) -> Limiter {
+    Limiter::build_with_store(MemoryStore::new())
+        .limit(limit)
+        .period(period)
+        .finish()
+        .expect("limiter should build")
+}
+
+fn items(items: Vec<u32>) -> impl Stream<Item = u32, Error = Error> {
+    stream::iter_ok(items)
+}
+
+#[test]
+fn stream_under_limit_is_not_delayed() {
+    let limiter = limiter(3, Duration::from_secs(60));
+    let started = Instant::now();
+
+    let sent = block_on(items(vec![1, 2, 3]).throttle(&limiter, "partner").collect())
+        .expect("stream should succeed");
+
+    assert_eq!(sent, vec![1, 2, 3]);
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn stream_is_paced_by_limit() {
+    let limiter = limiter(2, Duration::from_secs(1));
+    let started = Instant::now();
+
+    let sent = block_on(
+        items(vec![1, 2, 3, 4])
+            .throttle(&limiter, "partner")
+            .collect(),
+    )
+    .expect("stream should succeed");
+
+    assert_eq!(sent, vec![1, 2, 3, 4]);
+    assert!(started.elapsed() >= Duration::from_millis(500));
+}
+
+#[test]
+fn streams_share_a_key() {
+    let limiter = limiter(2, Duration::from_secs(60));
+
+    let first = block_on(items(vec![1, 2]).throttle(&limiter, "partner").collect())
+        .expect("stream should succeed");
+    assert_eq!(first, vec![1, 2]);
+
+    let second = block_on(
+        items(vec![3])
+            .throttle(&limiter, "partner")
+            .max_wait(Duration::from_secs(1))
+            .collect(),
+    );
+    match second {
+        Err(Error::LimitExceeded(status)) => assert_eq!(status.count(), 3),
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn stream_retries_item_after_error() {
+    let limiter = limiter(1, Duration::from_secs(60));
+
+    let results = block_on(
+        items(vec![1, 2])
+            .throttle(&limiter, "partner")
+            .max_wait(Duration::from_secs(0))
+            .then(Ok::<_, ()>)
+            .take(3)
+            .collect(),
+    )
+    .expect("stream should not fail");
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(*results[0].as_ref().expect("first item should be sent"), 1);
+    for result in &results[1..] {
+        match result {
+            Err(Error::LimitExceeded(_)) => {}
+            other => panic!("expected limit exceeded, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn sink_is_paced_by_limit() {
+    let limiter = limiter(2, Duration::from_secs(1));
+    let sink = Vec::new()
+        .sink_map_err(|()| -> Error { unreachable!("a Vec sink never fails") })
+        .sink_throttle(&limiter, "partner");
+    let started = Instant::now();
+
+    let (sink, _) = block_on(sink.send_all(items(vec![1, 2, 3, 4]))).expect("sink should succeed");
+
+    assert_eq!(sink.get_ref().get_ref(), &vec![1, 2, 3, 4]);
+    assert!(started.elapsed() >= Duration::from_millis(500));
+}
@@ -0,0 +1,130 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![cfg(feature = "tracing")]
+
+use futures::Future;
+use limitation::{Limiter, MemoryStore};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::fmt::MakeWriter;
+
+#[macro_use]
+mod support;
+
+/// A writer which captures formatted trace output in memory.
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Capture {
+    fn output(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).expect("output should be UTF-8")
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Capture {
+    type Writer = Capture;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+fn traced<F: FnOnce()>(f: F) -> String {
+    let capture = Capture::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_writer(capture.clone())
+        .with_ansi(false)
+        .finish();
+
+    tracing::subscriber::with_default(subscriber, f);
+
+    capture.output()
+}
+
+fn traced_count(limiter: &Limiter, key: &str) -> String {
+    traced(|| {
+        limiter.count(key).wait().expect("should be under limit");
+    })
+}
+
+#[test]
+fn keys_are_redacted_by_default() {
+    let limiter = Limiter::build_with_store(MemoryStore::new())
+        .finish()
+        .expect("limiter should build");
+
+    let output = traced_count(&limiter, "token s3cr3t");
+
+    assert!(output.contains("request allowed"), "output: {}", output);
+    assert!(output.contains("key=redacted:"), "output: {}", output);
+    assert!(!output.contains("s3cr3t"), "output: {}", output);
+}
+
+#[test]
+fn redacted_keys_are_stable() {
+    let limiter = Limiter::build_with_store(MemoryStore::new())
+        .finish()
+        .expect("limiter should build");
+
+    let fingerprint = |output: String| {
+        output
+            .split_whitespace()
+            .find(|word| word.contains("key=redacted:"))
+            .map(|word| word.to_string())
+    };
+
+    let first = fingerprint(traced_count(&limiter, "alice"));
+    assert!(first.is_some());
+    assert_eq!(first, fingerprint(traced_count(&limiter, "alice")));
+    assert_ne!(first, fingerprint(traced_count(&limiter, "bob")));
+}
+
+#[test]
+fn keys_are_revealed_when_enabled() {
+    let limiter = Limiter::build_with_store(MemoryStore::new())
+        .trace_keys(true)
+        .finish()
+        .expect("limiter should build");
+
+    let output = traced_count(&limiter, "alice");
+
+    assert!(output.contains("key=alice"), "output: {}", output);
+}
+
+#[test]
+fn redis_replies_are_not_recorded() {
+    let server = redis_server!();
+    let limiter = Limiter::build(&server.url())
+        .namespace("api")
+        .finish()
+        .expect("limiter should build");
+    limiter
+        .count("token s3cr3t")
+        .wait()
+        .expect("should be under limit");
+
+    let output = traced(|| {
+        limiter.usage().wait().expect("usage should succeed");
+    });
+
+    assert!(
+        output.contains("redis round trip complete"),
+        "output: {}",
+        output
+    );
+    assert!(!output.contains("s3cr3t"), "output: {}", output);
+}
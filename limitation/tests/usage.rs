@@ -0,0 +1,98 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{Error, Limiter, ManualClock, MemoryStore, Status, Window};
+use std::time::Duration;
+
+mod support;
+
+use support::{builder, clock, START};
+
+fn limiter(store: &MemoryStore, clock: &ManualClock, namespace: &str) -> Limiter {
+    builder(store.clone(), clock)
+        .limit(10)
+        .namespace(namespace)
+        .finish()
+        .expect("limiter should build")
+}
+
+fn count(limiter: &Limiter, key: &str, times: usize) {
+    for _ in 0..times {
+        limiter.count(key).wait().expect("should be under limit");
+    }
+}
+
+fn counts(usage: &[(String, Status)]) -> Vec<(&str, usize)> {
+    usage
+        .iter()
+        .map(|(key, status)| (key.as_str(), status.count()))
+        .collect()
+}
+
+#[test]
+fn usage_lists_keys_in_namespace() {
+    let store = MemoryStore::new();
+    let clock = clock();
+    let api = limiter(&store, &clock, "api");
+    let web = limiter(&store, &clock, "web");
+
+    count(&api, "alice", 3);
+    count(&api, "bob", 1);
+    count(&web, "carol", 1);
+
+    let mut usage = api.usage().wait().expect("usage should succeed");
+    usage.sort_by(|(a, _), (b, _)| a.cmp(b));
+    assert_eq!(counts(&usage), vec![("alice", 3), ("bob", 1)]);
+    assert_eq!(usage[0].1.remaining(), 7);
+    assert_eq!(usage[0].1.reset_epoch_utc(), START as usize + 60);
+
+    clock.advance(Duration::from_secs(60));
+    assert!(api.usage().wait().expect("usage should succeed").is_empty());
+}
+
+#[test]
+fn top_ranks_by_count() {
+    let store = MemoryStore::new();
+    let clock = clock();
+    let limiter = limiter(&store, &clock, "api");
+
+    count(&limiter, "alice", 2);
+    count(&limiter, "bob", 5);
+    count(&limiter, "carol", 2);
+    count(&limiter, "dave", 1);
+
+    let top = limiter.top(3).wait().expect("top should succeed");
+    assert_eq!(counts(&top), vec![("bob", 5), ("alice", 2), ("carol", 2)]);
+}
+
+#[test]
+fn usage_of_calendar_window() {
+    let store = MemoryStore::new();
+    let clock = clock();
+    let limiter = Limiter::build_with_store(store)
+        .limit(10)
+        .window(Window::Daily)
+        .namespace("api")
+        .clock(clock.clone())
+        .finish()
+        .expect("limiter should build");
+
+    count(&limiter, "alice", 2);
+
+    let usage = limiter.usage().wait().expect("usage should succeed");
+    assert_eq!(counts(&usage), vec![("alice", 2)]);
+}
+
+#[test]
+fn usage_without_namespace() {
+    let limiter = Limiter::build_with_store(MemoryStore::new())
+        .finish()
+        .expect("limiter should build");
+
+    match limiter.usage().wait() {
+        Err(Error::Config(_)) => {}
+        other => panic!("expected config error, got {:?}", other),
+    }
+}
@@ -0,0 +1,106 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use futures::Future;
+use limitation::{Error, Limiter, ManualClock, MemoryStore};
+use std::time::{Duration, UNIX_EPOCH};
+
+mod support;
+
+use support::{builder, clock, count_ok, START};
+
+fn limiter(clock: &ManualClock) -> Limiter {
+    builder(MemoryStore::new(), clock)
+        .limit(2)
+        .finish()
+        .expect("limiter should build")
+}
+
+#[test]
+fn count_under_limit() {
+    let clock = clock();
+    let limiter = limiter(&clock);
+
+    let status = count_ok(&limiter, "alice");
+    assert_eq!(status.limit(), 2);
+    assert_eq!(status.remaining(), 1);
+    assert_eq!(status.count(), 1);
+    assert_eq!(status.window_start_epoch_utc(), START as usize);
+    assert_eq!(status.reset_epoch_utc(), START as usize + 60);
+    assert_eq!(status.retry_after(), Duration::from_secs(0));
+
+    clock.advance(Duration::from_secs(15));
+    let status = count_ok(&limiter, "alice");
+    assert_eq!(status.remaining(), 0);
+    assert_eq!(status.count(), 2);
+    assert_eq!(status.window_start_epoch_utc(), START as usize);
+    assert_eq!(status.reset_epoch_utc(), START as usize + 60);
+    assert_eq!(status.retry_after(), Duration::from_secs(45));
+}
+
+#[test]
+fn count_over_limit() {
+    let clock = clock();
+    let limiter = limiter(&clock);
+
+    count_ok(&limiter, "alice");
+    count_ok(&limiter, "alice");
+    clock.advance(Duration::from_secs(59));
+
+    match limiter.count("alice").wait() {
+        Err(Error::LimitExceeded(status)) => {
+            assert_eq!(status.remaining(), 0);
+            assert_eq!(status.count(), 3);
+            assert_eq!(status.reset_epoch_utc(), START as usize + 60);
+            assert_eq!(status.retry_after(), Duration::from_secs(1));
+        }
+        other => panic!("expected limit exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn status_system_times() {
+    let clock = clock();
+    let limiter = limiter(&clock);
+
+    clock.advance(Duration::from_secs(5));
+    let status = count_ok(&limiter, "alice");
+
+    assert_eq!(
+        status.window_start_time(),
+        UNIX_EPOCH + Duration::from_secs(START + 5)
+    );
+    assert_eq!(
+        status.reset_time(),
+        UNIX_EPOCH + Duration::from_secs(START + 65)
+    );
+}
+
+#[test]
+fn window_resets_at_period_boundary() {
+    let clock = clock();
+    let limiter = limiter(&clock);
+
+    count_ok(&limiter, "alice");
+    count_ok(&limiter, "alice");
+    clock.advance(Duration::from_secs(60));
+
+    let status = count_ok(&limiter, "alice");
+    assert_eq!(status.remaining(), 1);
+    assert_eq!(status.count(), 1);
+    assert_eq!(status.window_start_epoch_utc(), START as usize + 60);
+    assert_eq!(status.reset_epoch_utc(), START as usize + 120);
+}
+
+#[test]
+fn keys_are_counted_independently() {
+    let clock = clock();
+    let limiter = limiter(&clock);
+
+    count_ok(&limiter, "alice");
+    count_ok(&limiter, "alice");
+
+    let status = count_ok(&limiter, "bob");
+    assert_eq!(status.remaining(), 1);
+}